@@ -0,0 +1,112 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Compression formats that CSV files are read from transparently.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    fn from_magic(magic: &[u8]) -> Option<Compression> {
+        if magic.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else if magic.starts_with(b"BZh") {
+            Some(Compression::Bzip2)
+        } else {
+            None
+        }
+    }
+
+    fn from_extension(path: &Path) -> Option<Compression> {
+        match path.extension()?.to_str()? {
+            "gz" | "gzip" => Some(Compression::Gzip),
+            "zst" | "zstd" => Some(Compression::Zstd),
+            "bz2" | "bzip2" => Some(Compression::Bzip2),
+            _ => None,
+        }
+    }
+
+    /// Shell command that decompresses to stdout, for use in dump files.
+    #[cfg(feature = "converters")]
+    pub fn command(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip -dc",
+            Compression::Zstd => "zstd -dc",
+            Compression::Bzip2 => "bzip2 -dc",
+        }
+    }
+}
+
+/// Work out if a file is compressed from its magic bytes, or from its extension
+/// when it is too short to have any.
+pub fn detect(path: &Path) -> std::io::Result<Option<Compression>> {
+    let mut magic = vec![];
+    File::open(path)?.take(4).read_to_end(&mut magic)?;
+    if magic.len() < 4 {
+        return Ok(Compression::from_magic(&magic).or_else(|| Compression::from_extension(path)));
+    }
+    Ok(Compression::from_magic(&magic))
+}
+
+/// Open a file, decompressing it on the fly if needed.
+pub fn open(path: &Path) -> std::io::Result<Box<dyn Read + Send>> {
+    let file = File::open(path)?;
+    Ok(match detect(path)? {
+        Some(Compression::Gzip) => Box::new(flate2::read::MultiGzDecoder::new(file)),
+        Some(Compression::Zstd) => Box::new(
+            ruzstd::decoding::StreamingDecoder::new(file)
+                .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?,
+        ),
+        Some(Compression::Bzip2) => Box::new(bzip2::read::MultiBzDecoder::new(file)),
+        None => Box::new(file),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn detect_and_open() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+
+        let path = tmp_dir.path().join("data.csv.gz");
+        let mut encoder =
+            flate2::write::GzEncoder::new(File::create(&path).unwrap(), Default::default());
+        encoder.write_all(b"a,b\n1,2\n").unwrap();
+        encoder.finish().unwrap();
+        assert_eq!(detect(&path).unwrap(), Some(Compression::Gzip));
+
+        let mut output = String::new();
+        open(&path).unwrap().read_to_string(&mut output).unwrap();
+        assert_eq!(output, "a,b\n1,2\n");
+
+        let path = tmp_dir.path().join("data.csv.bz2");
+        let mut encoder =
+            bzip2::write::BzEncoder::new(File::create(&path).unwrap(), Default::default());
+        encoder.write_all(b"a,b\n1,2\n").unwrap();
+        encoder.finish().unwrap();
+        assert_eq!(detect(&path).unwrap(), Some(Compression::Bzip2));
+
+        let mut output = String::new();
+        open(&path).unwrap().read_to_string(&mut output).unwrap();
+        assert_eq!(output, "a,b\n1,2\n");
+
+        // A plain file named like a compressed one is read as is.
+        let path = tmp_dir.path().join("plain.csv.gz");
+        std::fs::write(&path, b"a,b\n1,2\n").unwrap();
+        assert_eq!(detect(&path).unwrap(), None);
+
+        assert_eq!(
+            Compression::from_magic(&[0x28, 0xb5, 0x2f, 0xfd]),
+            Some(Compression::Zstd)
+        );
+    }
+}
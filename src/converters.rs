@@ -1,22 +1,34 @@
+use crate::compression;
 use crate::describe;
+use crate::describer::{
+    DateOrder, Detector, MAX_ENUM_VALUES, NumberFormat, check_value, find_detector, needs_iso,
+    parse_boolean, to_iso,
+};
+use crate::encoding;
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
 use csv::ReaderBuilder;
 use csv::Writer;
+use encoding_rs::{Encoding, UTF_8};
 use minijinja::Environment;
 use postgres::{Client, NoTls};
 use rusqlite::Connection;
 use spreadsheet_ods::OdsError;
+use spreadsheet_ods::condition::Condition;
+use spreadsheet_ods::validation::{Validation, ValidationRef};
 
 use rand::distr::{Alphanumeric, SampleString};
-use rust_xlsxwriter::{Format, Workbook};
+use rust_xlsxwriter::{DataValidation, ExcelDateTime, Format, Workbook};
 use serde_json::{Value, json};
 use snafu::prelude::*;
 use snafu::{Snafu, ensure};
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt::Write as fmt_write;
 use std::fs::{File, canonicalize};
 use std::io::BufReader;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tempfile::TempDir;
 use typed_builder::TypedBuilder;
 
@@ -29,7 +41,7 @@ use arrow::error::ArrowError;
 #[cfg(feature = "parquet")]
 use parquet::{
     arrow::ArrowWriter, basic::Compression, errors::ParquetError,
-    file::properties::WriterProperties,
+    file::properties::WriterProperties, schema::types::ColumnPath,
 };
 
 #[non_exhaustive]
@@ -92,6 +104,10 @@ pub enum Error {
     #[snafu(display("Postgres Error: {}", source))]
     PostgresError { source: postgres::Error },
 
+    #[cfg(feature = "tls")]
+    #[snafu(display("TLS Error: {}", source))]
+    TlsError { source: native_tls::Error },
+
     #[snafu(display("Error with writing XLSX file"))]
     XLSXError { source: rust_xlsxwriter::XlsxError },
 
@@ -104,6 +120,9 @@ pub enum Error {
         envvar: String,
     },
 
+    #[snafu(display("Unknown encoding {}", encoding))]
+    UnknownEncoding { encoding: String },
+
     #[snafu(display("Delimiter not valid utf-8"))]
     DelimeiterError { source: std::str::Utf8Error },
 
@@ -162,6 +181,68 @@ pub struct Options {
     pub truncate: bool,
     #[builder(default)]
     pub all_strings: bool,
+    #[builder(default = true)]
+    pub has_header: bool,
+    #[builder(default)]
+    pub field_names: Vec<String>,
+    #[builder(default)]
+    pub encoding: Option<String>,
+    #[builder(default)]
+    pub missing_values: Vec<String>,
+    #[builder(default)]
+    pub decimal_char: Option<char>,
+    #[builder(default)]
+    pub group_char: Option<char>,
+    #[builder(default = true)]
+    pub bare_number: bool,
+    #[builder(default)]
+    pub date_order: Option<DateOrder>,
+    #[builder(default)]
+    pub datetime_formats: Vec<String>,
+    #[builder(default)]
+    pub date_formats: Vec<String>,
+    #[builder(default)]
+    pub time_formats: Vec<String>,
+    #[builder(default)]
+    pub max_invalid_ratio: f64,
+    #[builder(default)]
+    pub rejects_csv: String,
+    #[builder(default)]
+    pub detect_keys: bool,
+    #[builder(default)]
+    pub detect_foreign_keys: bool,
+    #[builder(default)]
+    pub detect_enums: bool,
+    #[builder(default)]
+    pub constraints: bool,
+    #[builder(default)]
+    pub unique_constraints: bool,
+    #[builder(default)]
+    pub detect_formats: bool,
+    #[builder(default)]
+    pub detectors: Vec<Arc<dyn Detector>>,
+    #[builder(default)]
+    pub strict: bool,
+    #[builder(default)]
+    pub indexes: HashMap<String, Vec<Vec<String>>>,
+    #[builder(default)]
+    pub upsert: bool,
+    #[builder(default)]
+    pub upsert_csv: String,
+    #[builder(default)]
+    pub transaction: bool,
+    #[builder(default)]
+    pub swap_schema: bool,
+    #[builder(default)]
+    pub ssl_mode: String,
+    #[builder(default)]
+    pub ssl_root_cert: String,
+    #[builder(default)]
+    pub ssl_cert: String,
+    #[builder(default)]
+    pub ssl_key: String,
+    #[builder(default)]
+    pub dump_inline: bool,
 }
 
 lazy_static::lazy_static! {
@@ -351,6 +432,13 @@ fn merge_datapackage_json(mut base: Value, mut merger: Value) -> Result<Value, E
                     if field_type != base_type || base_type.is_empty() || field_type.is_empty() {
                         base_fieldinfo.insert("type".to_string(), "string".into());
                     }
+                    if base_fieldinfo["type"] == "string"
+                        && base_fieldinfo.get("format") != field_value.get("format")
+                    {
+                        base_fieldinfo.insert("format".to_string(), "string".into());
+                    }
+                    merge_numeric_hints(base_fieldinfo, field_value);
+                    merge_constraints(base_fieldinfo, field_value);
 
                     let base_count = base_fieldinfo["count"].as_u64().unwrap_or_default();
                     let field_count = field_value["count"].as_u64().unwrap_or_default();
@@ -366,6 +454,130 @@ fn merge_datapackage_json(mut base: Value, mut merger: Value) -> Result<Value, E
     Ok(base)
 }
 
+/// Loosen the constraints of a merged field so values from both fit. Ranges
+/// and lengths are widened and `required` and `unique` kept only when both
+/// have them. Enums are the union of both, unless one side has none or there
+/// are too many. A constraint only one side has is dropped.
+fn merge_constraints(base: &mut serde_json::Map<String, Value>, field: &Value) {
+    let numeric = matches!(
+        base.get("type").and_then(Value::as_str),
+        Some("integer" | "number")
+    );
+    let Some(constraints) = base.get_mut("constraints").and_then(Value::as_object_mut) else {
+        return;
+    };
+    let field_constraints = &field["constraints"];
+    for key in ["required", "unique"] {
+        if field_constraints[key] != true {
+            constraints.remove(key);
+        }
+    }
+    let widen = |key: &str, wider: fn(f64, f64) -> bool| {
+        let base_value = constraints.get(key)?;
+        let field_value = &field_constraints[key];
+        match (base_value.as_f64(), field_value.as_f64()) {
+            (Some(base_number), Some(field_number)) if wider(field_number, base_number) => {
+                Some(field_value.clone())
+            }
+            (Some(_), Some(_)) => Some(base_value.clone()),
+            _ => None,
+        }
+    };
+    let minimum = widen("minimum", |field, base| field < base).filter(|_| numeric);
+    let maximum = widen("maximum", |field, base| field > base).filter(|_| numeric);
+    let min_length = widen("minLength", |field, base| field < base);
+    let max_length = widen("maxLength", |field, base| field > base);
+    for (key, value) in [
+        ("minimum", minimum),
+        ("maximum", maximum),
+        ("minLength", min_length),
+        ("maxLength", max_length),
+    ] {
+        match value {
+            Some(value) => constraints.insert(key.into(), value),
+            None => constraints.remove(key),
+        };
+    }
+
+    let field_enum = field_constraints["enum"].as_array();
+    if let Some(base_enum) = constraints.get_mut("enum").and_then(Value::as_array_mut) {
+        match field_enum {
+            Some(field_enum) => {
+                for value in field_enum {
+                    if !base_enum.contains(value) {
+                        base_enum.push(value.clone());
+                    }
+                }
+                if base_enum.len() > MAX_ENUM_VALUES {
+                    constraints.remove("enum");
+                }
+            }
+            None => {
+                constraints.remove("enum");
+            }
+        }
+    }
+    if constraints.is_empty() {
+        base.remove("constraints");
+    }
+}
+
+/// Widen the size hints of a merged numeric field so values from both fit.
+fn merge_numeric_hints(base: &mut serde_json::Map<String, Value>, field: &Value) {
+    let hints = |field: &serde_json::Map<String, Value>| {
+        (
+            field.get("bitWidth").and_then(Value::as_u64),
+            field.get("precision").and_then(Value::as_u64),
+            field.get("scale").and_then(Value::as_u64),
+        )
+    };
+    let (base_width, base_precision, base_scale) = hints(base);
+    let field = field.as_object().cloned().unwrap_or_default();
+    let (field_width, field_precision, field_scale) = hints(&field);
+    for key in ["bitWidth", "precision", "scale"] {
+        base.remove(key);
+    }
+
+    match base.get("type").and_then(Value::as_str) {
+        Some("integer") => {
+            // Integers wider than 64 bits only have a precision.
+            let digits = |width: Option<u64>, precision: Option<u64>| match width {
+                Some(_) => Some(19),
+                None => precision,
+            };
+            match (base_width, field_width) {
+                (Some(base_width), Some(field_width)) => {
+                    base.insert("bitWidth".into(), base_width.max(field_width).into());
+                }
+                _ => {
+                    if let (Some(base_digits), Some(field_digits)) = (
+                        digits(base_width, base_precision),
+                        digits(field_width, field_precision),
+                    ) {
+                        base.insert("precision".into(), base_digits.max(field_digits).into());
+                    }
+                }
+            }
+        }
+        Some("number") => {
+            if let (
+                Some(base_precision),
+                Some(base_scale),
+                Some(field_precision),
+                Some(field_scale),
+            ) = (base_precision, base_scale, field_precision, field_scale)
+            {
+                let scale = base_scale.max(field_scale);
+                let integer_digits = (base_precision.saturating_sub(base_scale))
+                    .max(field_precision.saturating_sub(field_scale));
+                base.insert("precision".into(), (integer_digits + scale).into());
+                base.insert("scale".into(), scale.into());
+            }
+        }
+        _ => {}
+    }
+}
+
 pub fn merge_datapackage_jsons(datapackages: Vec<String>) -> Result<Value, Error> {
     ensure!(
         datapackages.len() > 1,
@@ -473,7 +685,17 @@ pub fn merge_datapackage_with_options(
         filename: output_path.to_string_lossy(),
     })?;
 
-    let merged_datapackage_json = merge_datapackage_jsons(datapackages.clone())?;
+    let mut merged_datapackage_json = merge_datapackage_jsons(datapackages.clone())?;
+
+    // The merged CSVs are always written out as UTF-8.
+    for resource in merged_datapackage_json["resources"]
+        .as_array_mut()
+        .expect("we know its an array")
+    {
+        if let Some(resource) = resource.as_object_mut() {
+            resource.remove("encoding");
+        }
+    }
 
     let path = PathBuf::from(&output_path);
 
@@ -609,9 +831,11 @@ fn merge_one_resource(
             for (num, name) in part_field_names.iter().enumerate() {
                 resource_fields.insert((*name).to_owned(), num);
             }
-            let csv_reader = get_csv_reader_builder(options, part_resource)
-                .from_path(&csv_path)
-                .unwrap();
+            let csv_reader = get_csv_reader_builder(options, part_resource).from_reader(open_csv(
+                &csv_path,
+                options,
+                part_resource,
+            )?);
             writer = write_merged_csv(csv_reader, writer, &resource_fields, &output_fields)?;
         }
 
@@ -629,11 +853,11 @@ fn merge_one_resource(
 }
 
 /// Append a part CSV's body (everything after its one header line) to `out`,
-/// byte-for-byte. The header is a single physical line (field names never
+/// byte-for-byte once decompressed. The header is a single physical line (field names never
 /// contain newlines), so read_until('\n') consumes it; io::copy moves the rest.
 fn copy_body_after_header(csv_path: &std::path::Path, out: &mut File) -> Result<(), Error> {
     use std::io::BufRead;
-    let mut reader = BufReader::new(File::open(csv_path).context(IoSnafu {
+    let mut reader = BufReader::new(compression::open(csv_path).context(IoSnafu {
         filename: csv_path.to_string_lossy(),
     })?);
     let mut header = Vec::new();
@@ -651,8 +875,10 @@ fn can_copy_csv_body(options: &Options, resource: &Value) -> bool {
     delimiter == b','
         && quote == b'"'
         && double_quote
-        && options.escape.is_none()
+        && csv_has_header(options, resource)
+        && csv_escape(options, resource).is_none()
         && options.comment.is_none()
+        && matches!(csv_encoding(options, resource), Ok(encoding) if encoding == UTF_8)
 }
 
 fn extract_csv_file(
@@ -679,12 +905,462 @@ fn get_csv_reader_builder(options: &Options, resource: &Value) -> csv::ReaderBui
         .delimiter(delimiter)
         .quote(quote)
         .double_quote(double_quote)
-        .escape(options.escape)
-        .comment(options.comment);
+        .escape(csv_escape(options, resource))
+        .comment(options.comment)
+        .has_headers(csv_has_header(options, resource));
 
     reader_builder
 }
 
+fn csv_escape(options: &Options, resource: &Value) -> Option<u8> {
+    if let Some(dialect_escape) = resource["dialect"]["escapeChar"].as_str() {
+        if dialect_escape.len() == 1 {
+            return dialect_escape.as_bytes().first().copied();
+        }
+    };
+    options.escape
+}
+
+fn csv_encoding(options: &Options, resource: &Value) -> Result<&'static Encoding, Error> {
+    let label = resource["encoding"]
+        .as_str()
+        .or(options.encoding.as_deref())
+        .unwrap_or("utf-8");
+    encoding::for_label(label).context(UnknownEncodingSnafu { encoding: label })
+}
+
+/// Open a resource's CSV as UTF-8, transcoding from its `encoding`.
+fn open_csv(
+    csv_path: &Path,
+    options: &Options,
+    resource: &Value,
+) -> Result<encoding::Input, Error> {
+    let encoding = csv_encoding(options, resource)?;
+    encoding::open(csv_path, encoding).context(IoSnafu {
+        filename: csv_path.to_string_lossy(),
+    })
+}
+
+/// Values that stand for a missing value, from the resource's Table Schema
+/// `missingValues` or else the `missing_values` option.
+fn missing_values(options: &Options, resource: &Value) -> Vec<String> {
+    if let Some(values) = resource["schema"]["missingValues"].as_array() {
+        return values
+            .iter()
+            .filter_map(|value| value.as_str().map(str::to_owned))
+            .collect();
+    }
+    let mut values = vec![String::new()];
+    values.extend(options.missing_values.iter().cloned());
+    values
+}
+
+/// Number format of each column, for numeric fields not written as plain numbers.
+fn number_formats(resource: &Value) -> Vec<Option<NumberFormat>> {
+    let Some(fields) = resource["schema"]["fields"].as_array() else {
+        return vec![];
+    };
+    fields
+        .iter()
+        .map(|field| {
+            let format = NumberFormat::from_field(field);
+            let numeric = ["number", "integer"].contains(&field["type"].as_str().unwrap_or(""));
+            (numeric && !format.is_default()).then_some(format)
+        })
+        .collect()
+}
+
+/// Allowed values of each column, for fields with an enum constraint.
+fn enum_values(resource: &Value) -> Vec<Option<Vec<String>>> {
+    let Some(fields) = resource["schema"]["fields"].as_array() else {
+        return vec![];
+    };
+    fields
+        .iter()
+        .map(|field| {
+            field["constraints"]["enum"].as_array().map(|values| {
+                values
+                    .iter()
+                    .map(|value| match value.as_str() {
+                        Some(value) => value.to_owned(),
+                        None => value.to_string(),
+                    })
+                    .collect()
+            })
+        })
+        .collect()
+}
+
+fn sql_list(values: Vec<String>) -> String {
+    values
+        .iter()
+        .map(|value| format!("'{}'", value.replace('\'', "''")))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A value as a plain number if its column has a number format.
+fn normalise_number<'a>(value: &'a str, format: Option<&NumberFormat>) -> Cow<'a, str> {
+    match format.and_then(|format| format.normalise(value)) {
+        Some(number) => number,
+        None => Cow::Borrowed(value),
+    }
+}
+
+/// A value that did not fit its field and was loaded as NULL.
+struct Reject {
+    table: String,
+    row: usize,
+    field: String,
+    value: String,
+}
+
+/// Tidies values on their way into a backend. Missing values become NULL,
+/// numbers in a locale format become plain numbers and dates, datetimes and
+/// times are parsed with their format and written in ISO 8601. With `max_invalid_ratio`,
+/// or for fields whose stats record invalid values, values that do not fit
+/// their field become NULL too and are kept as rejects.
+struct Cleaner {
+    table: String,
+    field_names: Vec<String>,
+    missing_values: Vec<String>,
+    number_formats: Vec<Option<NumberFormat>>,
+    field_types: Vec<Option<(String, String)>>,
+    temporal_formats: Vec<Option<(String, String)>>,
+    detectors: Vec<Arc<dyn Detector>>,
+    rejects: Vec<Reject>,
+}
+
+impl Cleaner {
+    fn new(options: &Options, resource: &Value) -> Cleaner {
+        let fields = resource["schema"]["fields"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        let field_types = fields
+            .iter()
+            .map(|field| {
+                let field_type = field["type"].as_str().unwrap_or("string");
+                let format = field["format"].as_str().unwrap_or("");
+                // Text with a format like `uuid` may go into a column of a native type.
+                let checked = field_type != "string" || !["", "string"].contains(&format);
+                // A field described with invalid values has them whatever the options.
+                let invalid = options.max_invalid_ratio > 0.0
+                    || field["stats"]["invalid_count"]
+                        .as_u64()
+                        .is_some_and(|count| count > 0);
+                (invalid && checked).then(|| (field_type.to_owned(), format.to_owned()))
+            })
+            .collect();
+        let temporal_formats = fields
+            .iter()
+            .map(|field| {
+                let field_type = field["type"].as_str().unwrap_or("string");
+                let format = field["format"].as_str().unwrap_or("");
+                (needs_iso(field_type, format)
+                    && find_detector(&options.detectors, field_type, format).is_none())
+                .then(|| (field_type.to_owned(), format.to_owned()))
+            })
+            .collect();
+        Cleaner {
+            table: resource["name"].as_str().unwrap_or_default().to_owned(),
+            field_names: fields
+                .iter()
+                .map(|field| field["name"].as_str().unwrap_or_default().to_owned())
+                .collect(),
+            missing_values: missing_values(options, resource),
+            number_formats: number_formats(resource),
+            field_types,
+            temporal_formats,
+            detectors: options.detectors.clone(),
+            rejects: vec![],
+        }
+    }
+
+    /// Whether backends that read the CSV directly need it rewritten first.
+    fn needs_rewrite(&self) -> bool {
+        self.missing_values.iter().any(|value| !value.is_empty())
+            || self.number_formats.iter().any(Option::is_some)
+            || self.field_types.iter().any(Option::is_some)
+            || self.temporal_formats.iter().any(Option::is_some)
+    }
+
+    /// The value to load, or `None` for NULL. Rows count from 1 after any header.
+    fn clean<'a>(&mut self, row: usize, column: usize, value: &'a str) -> Option<Cow<'a, str>> {
+        if self.missing_values.iter().any(|missing| missing == value) {
+            return None;
+        }
+        let number_format = self.number_formats.get(column).and_then(Option::as_ref);
+        if let Some(Some((field_type, format))) = self.field_types.get(column)
+            && !match find_detector(&self.detectors, field_type, format) {
+                Some(detector) => detector.check(value),
+                None => check_value(
+                    field_type,
+                    format,
+                    value,
+                    number_format.unwrap_or(&NumberFormat::default()),
+                ),
+            }
+        {
+            self.rejects.push(Reject {
+                table: self.table.clone(),
+                row,
+                field: self.field_names.get(column).cloned().unwrap_or_default(),
+                value: value.to_owned(),
+            });
+            return None;
+        }
+        if let Some(Some((field_type, format))) = self.temporal_formats.get(column)
+            && let Some(iso) = to_iso(field_type, format, value)
+        {
+            return Some(Cow::Owned(iso));
+        }
+        Some(normalise_number(value, number_format))
+    }
+}
+
+/// Write a CSV back out in the same dialect with its values cleaned, for
+/// backends that read the file themselves.
+fn write_clean_csv(
+    csv_path: &Path,
+    writer: impl std::io::Write,
+    cleaner: &mut Cleaner,
+    options: &Options,
+    resource: &Value,
+    quote_style: csv::QuoteStyle,
+) -> Result<(), Error> {
+    let (delimiter, quote, double_quote) = csv_dialect(options, resource);
+    let csv_reader = get_csv_reader_builder(options, resource)
+        .has_headers(false)
+        .from_reader(open_csv(csv_path, options, resource)?);
+    let mut csv_writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .quote(quote)
+        .double_quote(double_quote && csv_escape(options, resource).is_none())
+        .escape(csv_escape(options, resource).unwrap_or(b'\\'))
+        .quote_style(quote_style)
+        .from_writer(writer);
+
+    let has_header = csv_has_header(options, resource);
+    for (row_num, row) in csv_reader.into_records().enumerate() {
+        let row = row.context(CSVSnafu {
+            filename: csv_path.to_string_lossy(),
+        })?;
+        let row: Vec<Cow<str>> = if has_header && row_num == 0 {
+            row.iter().map(Cow::Borrowed).collect()
+        } else {
+            let row_num = if has_header { row_num } else { row_num + 1 };
+            row.iter()
+                .enumerate()
+                .map(|(index, value)| cleaner.clean(row_num, index, value).unwrap_or_default())
+                .collect()
+        };
+        csv_writer
+            .write_record(row.iter().map(|value| value.as_bytes()))
+            .context(CSVSnafu {
+                filename: csv_path.to_string_lossy(),
+            })?;
+    }
+    csv_writer.flush().context(IoSnafu {
+        filename: csv_path.to_string_lossy(),
+    })?;
+    Ok(())
+}
+
+/// Log and write out values that were loaded as NULL because they did not fit.
+fn write_rejects(options: &Options, rejects: &[Reject]) -> Result<(), Error> {
+    if rejects.is_empty() {
+        return Ok(());
+    }
+    log::warn!(
+        "{} values did not fit their field type and were loaded as NULL",
+        rejects.len()
+    );
+    if options.rejects_csv.is_empty() {
+        return Ok(());
+    }
+
+    let mut writer = Writer::from_path(&options.rejects_csv).context(CSVSnafu {
+        filename: &options.rejects_csv,
+    })?;
+    writer
+        .write_record(["table", "row", "field", "value"])
+        .context(CSVSnafu {
+            filename: &options.rejects_csv,
+        })?;
+    for reject in rejects {
+        writer
+            .write_record([
+                reject.table.as_str(),
+                &reject.row.to_string(),
+                &reject.field,
+                &reject.value,
+            ])
+            .context(CSVSnafu {
+                filename: &options.rejects_csv,
+            })?;
+    }
+    writer.flush().context(IoSnafu {
+        filename: &options.rejects_csv,
+    })?;
+    Ok(())
+}
+
+/// Rows an upsert added or changed in a table. Rows that conflicted with a
+/// table whose columns are all in its key are skipped.
+struct Upsert {
+    table: String,
+    inserted: u64,
+    updated: u64,
+    skipped: u64,
+}
+
+/// Log and write out how many rows each upsert inserted, updated and skipped.
+fn write_upserts(options: &Options, upserts: &[Upsert]) -> Result<(), Error> {
+    for upsert in upserts {
+        log::info!(
+            "{}: {} rows inserted, {} rows updated, {} rows skipped",
+            upsert.table,
+            upsert.inserted,
+            upsert.updated,
+            upsert.skipped
+        );
+    }
+    if options.upsert_csv.is_empty() {
+        return Ok(());
+    }
+
+    let mut writer = Writer::from_path(&options.upsert_csv).context(CSVSnafu {
+        filename: &options.upsert_csv,
+    })?;
+    writer
+        .write_record(["table", "inserted", "updated", "skipped"])
+        .context(CSVSnafu {
+            filename: &options.upsert_csv,
+        })?;
+    for upsert in upserts {
+        writer
+            .write_record([
+                upsert.table.as_str(),
+                &upsert.inserted.to_string(),
+                &upsert.updated.to_string(),
+                &upsert.skipped.to_string(),
+            ])
+            .context(CSVSnafu {
+                filename: &options.upsert_csv,
+            })?;
+    }
+    writer.flush().context(IoSnafu {
+        filename: &options.upsert_csv,
+    })?;
+    Ok(())
+}
+
+/// The primary key columns of an existing SQLite table, in key order.
+fn sqlite_primary_key(conn: &Connection, table: &str) -> Result<Vec<String>, Error> {
+    let mut query = conn
+        .prepare("select name from pragma_table_info(?) where pk > 0 order by pk")
+        .context(RusqliteSnafu {
+            message: "Error peparing sql",
+        })?;
+    query
+        .query_map([table], |row| row.get(0))
+        .context(RusqliteSnafu {
+            message: "Error fetching table schema: ",
+        })?
+        .collect::<Result<Vec<_>, _>>()
+        .context(RusqliteSnafu {
+            message: "Error fetching table schema: ",
+        })
+}
+
+/// The primary key columns of an existing Postgres table, in key order.
+fn postgres_primary_key(client: &mut Client, schema_table: &str) -> Result<Vec<String>, Error> {
+    let rows = client
+        .query(
+            "SELECT a.attname::TEXT FROM pg_index i \
+             JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = ANY(i.indkey) \
+             WHERE i.indrelid = to_regclass($1) AND i.indisprimary \
+             ORDER BY array_position(i.indkey::int2[], a.attnum)",
+            &[&schema_table],
+        )
+        .context(PostgresSnafu {})?;
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+}
+
+/// Statements dropping the `CHECK` constraints on a column of an existing
+/// Postgres table, or only its enum (`IN` list) ones.
+fn postgres_drop_checks(
+    client: &mut Client,
+    schema_table: &str,
+    column: &str,
+    enums_only: bool,
+) -> Result<Vec<String>, Error> {
+    let rows = client
+        .query(
+            "SELECT c.conname::TEXT FROM pg_constraint c \
+             JOIN pg_attribute a ON a.attrelid = c.conrelid AND a.attnum = ANY(c.conkey) \
+             WHERE c.conrelid = to_regclass($1) AND c.contype = 'c' AND a.attname = $2 \
+             AND (NOT $3 OR pg_get_constraintdef(c.oid) LIKE '%= ANY (%')",
+            &[&schema_table, &column, &enums_only],
+        )
+        .context(PostgresSnafu {})?;
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let name: String = row.get(0);
+            format!("ALTER TABLE {schema_table} DROP CONSTRAINT \"{name}\"")
+        })
+        .collect())
+}
+
+fn resource_primary_key(resource: &Value) -> Vec<String> {
+    match &resource["schema"]["primaryKey"] {
+        Value::String(field) => vec![field.clone()],
+        Value::Array(fields) => fields
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_owned)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// The `ON CONFLICT` clause that updates rows with the same primary key as a
+/// new row, or `None` if there is no primary key. The key is the one the
+/// table has, which can differ from the resource's when the table existed.
+fn upsert_clause(
+    resource: &Value,
+    primary_key: &[String],
+    quote: fn(&str) -> String,
+) -> Option<String> {
+    if primary_key.is_empty() {
+        return None;
+    }
+    let updates: Vec<String> = resource["schema"]["fields"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|field| field["name"].as_str())
+        .filter(|name| !primary_key.iter().any(|key| key == name))
+        .map(|name| format!("{0} = excluded.{0}", quote(name)))
+        .collect();
+    let primary_key: Vec<String> = primary_key.iter().map(|key| quote(key)).collect();
+    let action = if updates.is_empty() {
+        "DO NOTHING".to_owned()
+    } else {
+        format!("DO UPDATE SET {}", updates.join(", "))
+    };
+    Some(format!("ON CONFLICT ({}) {action}", primary_key.join(", ")))
+}
+
+fn csv_has_header(options: &Options, resource: &Value) -> bool {
+    if let Some(dialect_header) = resource["dialect"]["header"].as_bool() {
+        return dialect_header;
+    };
+    options.has_header
+}
+
 fn csv_dialect(options: &Options, resource: &Value) -> (u8, u8, bool) {
     let mut delimiter = options.delimiter.unwrap_or(b',');
     if let Some(dialect_delimiter) = resource["dialect"]["delimiter"].as_str() {
@@ -712,78 +1388,388 @@ fn rand() -> String {
     return Alphanumeric.sample_string(&mut rand::rng(), 5);
 }
 
-fn to_db_type(type_: String, format: String) -> String {
+fn to_db_type(
+    type_: String,
+    precision: Option<u32>,
+    scale: Option<u32>,
+    bit_width: Option<u32>,
+) -> String {
     match type_.as_str() {
         "string" => "TEXT".to_string(),
-        "date" => {
-            if POSTGRES_ALLOWED_DATE_FORMATS.contains(&format.as_str()) || format.is_empty() {
-                "TIMESTAMP".to_string()
-            } else {
-                "TEXT".into()
-            }
-        }
-        "datetime" => {
-            if POSTGRES_ALLOWED_DATE_FORMATS.contains(&format.as_str()) || format.is_empty() {
-                "TIMESTAMP".to_string()
-            } else {
-                "TEXT".into()
-            }
-        }
-        "datetime_tz" => {
-            if POSTGRES_ALLOWED_DATE_FORMATS.contains(&format.as_str()) || format.is_empty() {
-                "TIMESTAMP".to_string()
-            } else {
-                "TEXT".into()
-            }
-        }
-        "number" => "NUMERIC".to_string(),
+        "date" => "DATE".to_string(),
+        "time" => "TIME".to_string(),
+        "datetime" => "TIMESTAMP".to_string(),
+        "datetime_tz" => "TIMESTAMPTZ".to_string(),
+        "number" => match (precision, scale) {
+            (Some(precision), Some(scale)) => format!("NUMERIC({precision},{scale})"),
+            _ => "NUMERIC".to_string(),
+        },
         "object" => "JSONB".to_string(),
         "array" => "JSONB".to_string(),
-        "integer" => "BIGINT".to_string(),
+        "integer" => match (bit_width, precision) {
+            (Some(16), _) => "SMALLINT".to_string(),
+            (Some(32), _) => "INTEGER".to_string(),
+            (None, Some(precision)) => format!("NUMERIC({precision},0)"),
+            _ => "BIGINT".to_string(),
+        },
         "boolean" => "BOOL".to_string(),
         _ => "TEXT".to_string(),
     }
 }
 
-fn clean_field(_state: &minijinja::State, field: String) -> Result<String, minijinja::Error> {
-    if INVALID_REGEX.is_match(&field) {
-        return Ok(INVALID_REGEX.replace_all(&field, " ").to_string());
+fn field_db_type(field: &Value) -> String {
+    if field["type"] == "string"
+        && let Some(max_length) = field["constraints"]["maxLength"].as_u64()
+    {
+        return format!("VARCHAR({max_length})");
     }
-    Ok(field)
+    let hint = |key: &str| field[key].as_u64().and_then(|value| value.try_into().ok());
+    to_db_type(
+        field["type"].as_str().unwrap_or("string").into(),
+        hint("precision"),
+        hint("scale"),
+        hint("bitWidth"),
+    )
 }
 
-fn render_sqlite_table(value: Value) -> Result<String, Error> {
-    let sqlite_table = r#"
-    CREATE TABLE [{{title|default(name)}}] (
-        {% for field in schema.fields %}
-           {% if not loop.first %}, {% endif %}[{{field.name}}] {{ to_db_type(field.type, field.format) }} #nl
-        {% endfor %}
-        {% if schema.primaryKey is string %}
-           , PRIMARY KEY ([{{schema.primaryKey}}]) #nl
-        {% endif %}
-        {% if schema.primaryKey is sequence %}
-           , PRIMARY KEY ([{{schema.primaryKey | join("],[")}}]) #nl
-        {% endif %}
-        {% if schema.foreignKeys is sequence %}
-           {% for foreignKey in schema.foreignKeys %}
-              {% if foreignKey.fields is string %}
-                , FOREIGN KEY ([{{foreignKey.fields}}]) REFERENCES [{{foreignKey.reference.resource}}]([{{foreignKey.reference.fields}}]) #nl {% endif %} {% if foreignKey.fields is sequence %} , FOREIGN KEY ([{{foreignKey.fields | join("],[")}}])
-                  REFERENCES [{{foreignKey.reference.resource}}]([{{foreignKey.reference.fields | join("],[")}}]) #nl
-              {% endif %}
-           {% endfor %}
-        {% endif %}
-    ); #nl
+/// Postgres has native types for some text formats. Places are written
+/// `latitude, longitude` so are loaded as a `POINT` of (latitude, longitude).
+fn postgres_field_type(field: &Value) -> String {
+    if field["type"] == "string" {
+        match field["format"].as_str() {
+            Some("uuid") => return "UUID".into(),
+            Some("ipv4" | "ipv6") => return "INET".into(),
+            Some("latlong") => return "POINT".into(),
+            _ => {}
+        }
+    }
+    field_db_type(field)
+}
 
-    {% if schema.foreignKeys is sequence %}
-        {% for foreignKey in schema.foreignKeys %}
-            {% if foreignKey.fields is string %}
-              CREATE INDEX [idx_{{name}}_{{foreignKey.fields}}] ON [{{title|default(name)}}] ([{{foreignKey.fields}}]); #nl
-            {% endif %}
-            {% if foreignKey.fields is sequence %}
-              CREATE INDEX [idx_{{name}}_{{foreignKey.fields | join("_")}}] ON [{{title|default(name)}}] ([{{foreignKey.fields | join("],[")}}]); #nl
-            {% endif %}
+/// SQLite `STRICT` tables only allow `INTEGER`, `REAL`, `TEXT`, `BLOB` and
+/// `ANY`. Integers beyond 64 bits and numbers beyond 15 significant digits are
+/// loaded as text.
+fn to_strict_db_type(type_: String, bit_width: Option<u32>, precision: Option<u32>) -> String {
+    match type_.as_str() {
+        "integer" if bit_width.is_none() && precision.is_some() => "TEXT".to_string(),
+        "integer" | "boolean" => "INTEGER".to_string(),
+        "number" if precision.is_some_and(|precision| precision > 15) => "TEXT".to_string(),
+        "number" => "REAL".to_string(),
+        _ => "TEXT".to_string(),
+    }
+}
+
+/// SQLite's `NUMERIC` affinity turns long numbers into lossy reals, so
+/// integers beyond 64 bits and numbers beyond 15 significant digits get `TEXT`
+/// columns.
+fn to_sqlite_db_type(
+    type_: String,
+    precision: Option<u32>,
+    scale: Option<u32>,
+    bit_width: Option<u32>,
+) -> String {
+    match type_.as_str() {
+        "integer" if bit_width.is_none() && precision.is_some() => "TEXT".to_string(),
+        "number" if precision.is_some_and(|precision| precision > 15) => "TEXT".to_string(),
+        _ => to_db_type(type_, precision, scale, bit_width),
+    }
+}
+
+fn sqlite_field_db_type(field: &Value) -> String {
+    if field["type"] == "string" && field["constraints"]["maxLength"].is_u64() {
+        return field_db_type(field);
+    }
+    let hint = |key: &str| field[key].as_u64().and_then(|value| value.try_into().ok());
+    to_sqlite_db_type(
+        field["type"].as_str().unwrap_or("string").into(),
+        hint("precision"),
+        hint("scale"),
+        hint("bitWidth"),
+    )
+}
+
+fn strict_field_db_type(field: &Value) -> String {
+    let hint = |key: &str| field[key].as_u64().and_then(|value| value.try_into().ok());
+    to_strict_db_type(
+        field["type"].as_str().unwrap_or("string").into(),
+        hint("bitWidth"),
+        hint("precision"),
+    )
+}
+
+fn postgres_type(field: minijinja::Value) -> String {
+    postgres_field_type(&serde_json::to_value(field).unwrap_or_default())
+}
+
+/// The type an existing column needs altering to, if any. Integer and
+/// temporal columns are widened, so dates fit a `TIMESTAMP` column, and sized
+/// numeric columns are loosened to plain `NUMERIC`.
+fn column_change(existing_type: &str, new_type: &str) -> Option<String> {
+    let integer_rank = |type_: &str| match type_ {
+        "smallint" | "int2" => Some(1),
+        "integer" | "int4" => Some(2),
+        "bigint" | "int8" => Some(3),
+        _ => None,
+    };
+    let temporal_rank = |type_: &str| match type_ {
+        "date" => Some(1),
+        "timestamp" => Some(2),
+        "timestamptz" => Some(3),
+        _ => None,
+    };
+    let existing_type = existing_type.to_lowercase();
+    let new_type = new_type.to_lowercase();
+    if existing_type == new_type || existing_type == "text" {
+        return None;
+    }
+    for rank in [integer_rank, temporal_rank] {
+        if let (Some(existing_rank), Some(new_rank)) = (rank(&existing_type), rank(&new_type)) {
+            return (new_rank > existing_rank).then(|| new_type.to_uppercase());
+        }
+    }
+    let base_type = |type_: &str| type_.split('(').next().unwrap_or_default().to_owned();
+    if base_type(&existing_type) == "numeric" && base_type(&new_type) == "numeric" {
+        return Some("NUMERIC".into());
+    }
+    Some("TEXT".into())
+}
+
+/// An existing SQLite `CREATE TABLE` statement with a column's declared type
+/// changed. `CHECK` constraints on the column are dropped as they were
+/// written for the old type.
+fn change_sqlite_column_type(
+    table_sql: &str,
+    name: &str,
+    existing_type: &str,
+    new_type: &str,
+) -> Option<String> {
+    let name_pattern = regex::escape(name);
+    let column = regex::RegexBuilder::new(&format!(
+        r#"([(,]\s*)(\[{name_pattern}\]|"{name_pattern}"|`{name_pattern}`|{name_pattern})\s*{}"#,
+        regex::escape(existing_type)
+    ))
+    .case_insensitive(true)
+    .build()
+    .ok()?;
+    let found = column.captures(table_sql)?;
+    let whole = found.get(0)?;
+    let sql = format!(
+        "{}{}[{name}] {new_type}{}",
+        &table_sql[..whole.start()],
+        &found[1],
+        &table_sql[whole.end()..]
+    );
+    Some(drop_sqlite_checks(sql, |check| {
+        check.contains(&format!("[{name}]"))
+    }))
+}
+
+/// An existing SQLite `CREATE TABLE` statement without the `CHECK`
+/// constraints whose condition, with its parentheses, matches `drop`.
+fn drop_sqlite_checks(mut sql: String, drop: impl Fn(&str) -> bool) -> String {
+    let mut start = 0;
+    while let Some(found) = sql[start..].find(" CHECK (") {
+        let check_start = start + found;
+        let open = check_start + " CHECK ".len();
+        let mut depth = 0;
+        let mut check_end = None;
+        for (index, char) in sql[open..].char_indices() {
+            match char {
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        check_end = Some(open + index + 1);
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        let Some(check_end) = check_end else { break };
+        if drop(&sql[open..check_end]) {
+            sql.replace_range(check_start..check_end, "");
+            start = check_start;
+        } else {
+            start = check_end;
+        }
+    }
+    sql
+}
+
+/// The `CHECK (column IN (..))` an enum constraint is written as.
+fn is_sqlite_enum_check(check: &str, name: &str) -> bool {
+    check.starts_with(&format!("([{name}] IN ("))
+}
+
+/// SQLite can not change the type of a column, so the table is rebuilt: a
+/// copy is created with the new column types, the rows are copied across,
+/// the old table is dropped and the copy renamed. Foreign keys and other
+/// table constraints come with the `CREATE TABLE` statement and indexes and
+/// triggers are created again. Enum `CHECK` constraints on the loaded columns
+/// are dropped too, as new values would fail them. `None` if nothing changes.
+fn rebuild_sqlite_table(
+    conn: &Connection,
+    table: &str,
+    existing_columns: &HashMap<String, String>,
+    alter_columns: &Columns,
+    loaded_columns: &[&str],
+) -> Result<Option<String>, Error> {
+    let mut table_sql: String = conn
+        .query_row(
+            "select sql from sqlite_schema where type = 'table' and name = ?",
+            [table],
+            |row| row.get(0),
+        )
+        .context(RusqliteSnafu {
+            message: "Error fetching table schema: ",
+        })?;
+
+    let mut schema_query = conn
+        .prepare(
+            "select sql from sqlite_schema where tbl_name = ? and type in ('index', 'trigger') and sql is not null",
+        )
+        .context(RusqliteSnafu {
+            message: "Error peparing sql",
+        })?;
+    let recreate = schema_query
+        .query_map([table], |row| row.get::<_, String>(0))
+        .context(RusqliteSnafu {
+            message: "Error fetching table schema: ",
+        })?
+        .collect::<Result<Vec<_>, _>>()
+        .context(RusqliteSnafu {
+            message: "Error fetching table schema: ",
+        })?;
+
+    let without_enums = drop_sqlite_checks(table_sql.clone(), |check| {
+        loaded_columns
+            .iter()
+            .any(|name| is_sqlite_enum_check(check, name))
+    });
+    if alter_columns.is_empty() && without_enums == table_sql {
+        return Ok(None);
+    }
+    table_sql = without_enums;
+
+    for (name, type_) in alter_columns {
+        let existing_type = existing_columns.get(name).map(String::as_str);
+        table_sql = existing_type
+            .and_then(|existing_type| {
+                change_sqlite_column_type(&table_sql, name, existing_type, type_)
+            })
+            .context(DatapackageConvertSnafu {
+                message: format!("Could not change the type of column `{name}` in table `{table}`"),
+            })?;
+    }
+
+    // Booleans are stored as 0 and 1, so keep their meaning when widened to text.
+    let mut columns_query = conn
+        .prepare("select name, type from pragma_table_info(?) order by cid")
+        .context(RusqliteSnafu {
+            message: "Error peparing sql",
+        })?;
+    let (columns, values): (Vec<_>, Vec<_>) = columns_query
+        .query_map([table], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })
+        .context(RusqliteSnafu {
+            message: "Error fetching table schema: ",
+        })?
+        .collect::<Result<Vec<_>, _>>()
+        .context(RusqliteSnafu {
+            message: "Error fetching table schema: ",
+        })?
+        .into_iter()
+        .map(|(name, existing_type)| {
+            let to_text = alter_columns
+                .iter()
+                .any(|(altered, type_)| altered == &name && type_.eq_ignore_ascii_case("TEXT"));
+            let value = if to_text
+                && ["BOOL", "BOOLEAN"].contains(&existing_type.to_uppercase().as_str())
+            {
+                format!("CASE [{name}] WHEN 1 THEN 'true' WHEN 0 THEN 'false' ELSE [{name}] END")
+            } else {
+                format!("[{name}]")
+            };
+            (format!("[{name}]"), value)
+        })
+        .unzip();
+
+    let new_table = format!("_evolve_{table}");
+    let columns_start = table_sql.find('(').context(DatapackageConvertSnafu {
+        message: format!("Could not read the schema of table `{table}`"),
+    })?;
+
+    let mut sql = format!(
+        "CREATE TABLE [{new_table}] {};\n",
+        &table_sql[columns_start..]
+    );
+    writeln!(
+        sql,
+        "INSERT INTO [{new_table}]({}) SELECT {} FROM [{table}];",
+        columns.join(", "),
+        values.join(", ")
+    )
+    .unwrap();
+    writeln!(sql, "DROP TABLE [{table}];").unwrap();
+    writeln!(sql, "ALTER TABLE [{new_table}] RENAME TO [{table}];").unwrap();
+    for statement in recreate {
+        writeln!(sql, "{statement};").unwrap();
+    }
+    Ok(Some(sql))
+}
+
+fn clean_field(_state: &minijinja::State, field: String) -> Result<String, minijinja::Error> {
+    if INVALID_REGEX.is_match(&field) {
+        return Ok(INVALID_REGEX.replace_all(&field, " ").to_string());
+    }
+    Ok(field)
+}
+
+fn render_sqlite_table(value: Value, strict: bool) -> Result<String, Error> {
+    let sqlite_table = r#"
+    {% macro column_type(field) %}
+        {% if field.type == "string" and field.constraints is mapping and field.constraints.maxLength is number %}
+            {% if strict %}TEXT{% else %}VARCHAR({{field.constraints.maxLength}}){% endif %}
+        {% elif strict %}
+            {{ to_strict_db_type(field.type, field.bitWidth, field.precision) }}
+        {% else %}
+            {{ to_sqlite_db_type(field.type, field.precision, field.scale, field.bitWidth) }}
+        {% endif %}
+    {% endmacro %}
+
+    {% macro column_constraints(field) %}
+        {% if field.constraints is mapping %}
+            {% if field.type == "string" and field.constraints.maxLength is number %} CHECK (length([{{field.name}}]) <= {{field.constraints.maxLength}}){% endif %}
+            {% if field.constraints.required %} NOT NULL{% endif %}
+            {% if field.constraints.unique %} UNIQUE{% endif %}
+            {% if field.constraints.minimum is number %} CHECK ([{{field.name}}] >= {{field.constraints.minimum}}){% endif %}
+            {% if field.constraints.maximum is number %} CHECK ([{{field.name}}] <= {{field.constraints.maximum}}){% endif %}
+            {% if field.constraints.minLength %} CHECK (length([{{field.name}}]) >= {{field.constraints.minLength}}){% endif %}
+            {% if field.constraints.enum is sequence %} CHECK ([{{field.name}}] IN ({{field.constraints.enum|sql_list}})){% endif %}
+        {% endif %}
+    {% endmacro %}
+
+    CREATE TABLE [{{title|default(name)}}] (
+        {% for field in schema.fields %}
+           {% if not loop.first %}, {% endif %}[{{field.name}}] {{ column_type(field) }}{{ column_constraints(field) }} #nl
         {% endfor %}
-    {% endif %}
+        {% if schema.primaryKey is string %}
+           , PRIMARY KEY ([{{schema.primaryKey}}]) #nl
+        {% endif %}
+        {% if schema.primaryKey is sequence %}
+           , PRIMARY KEY ([{{schema.primaryKey | join("],[")}}]) #nl
+        {% endif %}
+        {% if schema.foreignKeys is sequence %}
+           {% for foreignKey in schema.foreignKeys %}
+              {% if foreignKey.fields is string %}
+                , FOREIGN KEY ([{{foreignKey.fields}}]) REFERENCES [{{foreignKey.reference.resource}}]([{{foreignKey.reference.fields}}]) #nl {% endif %} {% if foreignKey.fields is sequence %} , FOREIGN KEY ([{{foreignKey.fields | join("],[")}}])
+                  REFERENCES [{{foreignKey.reference.resource}}]([{{foreignKey.reference.fields | join("],[")}}]) #nl
+              {% endif %}
+           {% endfor %}
+        {% endif %}
+    ){% if strict %} STRICT{% endif %}; #nl
 
     "#;
     let sqlite_table = sqlite_table.replace("  ", "");
@@ -791,17 +1777,55 @@ fn render_sqlite_table(value: Value) -> Result<String, Error> {
     let sqlite_table = sqlite_table.replace("#nl", "\n");
 
     let mut env = Environment::new();
-    env.add_function("to_db_type", to_db_type);
+    env.add_function("to_sqlite_db_type", to_sqlite_db_type);
+    env.add_function("to_strict_db_type", to_strict_db_type);
+    env.add_filter("sql_list", sql_list);
     env.add_template("sqlite_resource", &sqlite_table).unwrap();
     let tmpl = env.get_template("sqlite_resource").unwrap();
-    tmpl.render(value).context(JinjaSnafu {})
+    tmpl.render(minijinja::context! {
+        strict => strict,
+        ..minijinja::Value::from_serialize(&value)
+    })
+    .context(JinjaSnafu {})
+}
+
+/// Indexes for foreign key columns and any extra indexes asked for, created
+/// once the data is loaded.
+fn render_sqlite_indexes(value: Value, indexes: &[Vec<String>]) -> Result<String, Error> {
+    let sqlite_indexes = r#"
+    {% if schema.foreignKeys is sequence %}
+        {% for foreignKey in schema.foreignKeys %}
+            {% if foreignKey.fields is string %}
+              CREATE INDEX IF NOT EXISTS [idx_{{name}}_{{foreignKey.fields}}] ON [{{title|default(name)}}] ([{{foreignKey.fields}}]); #nl
+            {% endif %}
+            {% if foreignKey.fields is sequence %}
+              CREATE INDEX IF NOT EXISTS [idx_{{name}}_{{foreignKey.fields | join("_")}}] ON [{{title|default(name)}}] ([{{foreignKey.fields | join("],[")}}]); #nl
+            {% endif %}
+        {% endfor %}
+    {% endif %}
+    {% for index in indexes %}
+        CREATE INDEX IF NOT EXISTS [idx_{{name}}_{{index | join("_")}}] ON [{{title|default(name)}}] ([{{index | join("],[")}}]); #nl
+    {% endfor %}
+    "#;
+    let sqlite_indexes = sqlite_indexes.replace("  ", "");
+    let sqlite_indexes = sqlite_indexes.replace('\n', "");
+    let sqlite_indexes = sqlite_indexes.replace("#nl", "\n");
+
+    let mut env = Environment::new();
+    env.add_template("sqlite_indexes", &sqlite_indexes).unwrap();
+    let tmpl = env.get_template("sqlite_indexes").unwrap();
+    tmpl.render(minijinja::context! {
+        indexes => indexes,
+        ..minijinja::Value::from_serialize(&value)
+    })
+    .context(JinjaSnafu {})
 }
 
 fn render_postgres_table(value: Value) -> Result<String, Error> {
     let postgres_table = r#"
     CREATE TABLE IF NOT EXISTS "{{title|default(name)}}" (
         {% for field in schema.fields %}
-           {% if not loop.first %}, {% endif %}"{{field.name|clean_field}}" {{to_db_type(field.type, field.format)}} #nl
+           {% if not loop.first %}, {% endif %}{% set db_type = postgres_type(field) %}"{{field.name|clean_field}}" {{db_type}}{% if field.constraints is mapping %}{% if field.constraints.required %} NOT NULL{% endif %}{% if field.constraints.unique %} UNIQUE{% endif %}{% if field.constraints.minimum is number %} CHECK ("{{field.name|clean_field}}" >= {{field.constraints.minimum}}){% endif %}{% if field.constraints.maximum is number %} CHECK ("{{field.name|clean_field}}" <= {{field.constraints.maximum}}){% endif %}{% if field.constraints.minLength and db_type is startingwith("VARCHAR") %} CHECK (length("{{field.name|clean_field}}") >= {{field.constraints.minLength}}){% endif %}{% if field.constraints.enum is sequence %} CHECK ("{{field.name|clean_field}}" IN ({{field.constraints.enum|sql_list}})){% endif %}{% endif %} #nl
         {% endfor %}
         {% if schema.primaryKey is string %}
            , PRIMARY KEY ("{{schema.primaryKey}}") #nl
@@ -839,132 +1863,65 @@ fn render_postgres_table(value: Value) -> Result<String, Error> {
     let postgres_table = postgres_table.replace("#nl", "\n");
 
     let mut env = Environment::new();
-    env.add_function("to_db_type", to_db_type);
+    env.add_function("postgres_type", postgres_type);
     env.add_function("rand", rand);
     env.add_filter("clean_field", clean_field);
+    env.add_filter("sql_list", sql_list);
     env.add_template("postgres_resource", &postgres_table)
         .unwrap();
     let tmpl = env.get_template("postgres_resource").unwrap();
     tmpl.render(value).context(JinjaSnafu {})
 }
 
-lazy_static::lazy_static! {
-    pub static ref POSTGRES_ALLOWED_DATE_FORMATS: Vec<&'static str> =
-    vec!(
-        "%Y-%m-%d %H:%M",
-        "%Y-%m-%d %H:%M:%S%.f",
-        "%Y-%m-%dT%H:%M:%S%.f",
-        "%Y-%m-%d %H:%M:%S.%f",
-        "%Y-%m-%d %I:%M:%S %P",
-        "%Y-%m-%d %I:%M %P",
-        "%Y %b %d %H:%M:%S",
-        "%B %d %Y %H:%M:%S",
-        "%B %d %Y %I:%M:%S %P",
-        "%B %d %Y %I:%M %P",
-        "%Y %b %d at %I:%M %P",
-        "%d %B %Y %H:%M:%S",
-        "%d %B %Y %H:%M",
-        "%d %B %Y %H:%M:%S.%f",
-        "%d %B %Y %I:%M:%S %P",
-        "%d %B %Y %I:%M %P",
-        "%Y-%m-%d %H:%M:%S%#z",
-        "%Y-%m-%d %H:%M:%S.%f%#z",
-        "%Y-%m-%d %H:%M%#z",
-        "%m/%d/%y %H:%M:%S",
-        "%m/%d/%y %H:%M",
-        "%m/%d/%y %H:%M:%S.%f",
-        "%m/%d/%y %I:%M:%S %P",
-        "%m/%d/%y %I:%M %P",
-        "%m/%d/%Y %H:%M:%S",
-        "%m/%d/%Y %H:%M",
-        "%m/%d/%Y %H:%M:%S.%f",
-        "%m/%d/%Y %I:%M:%S %P",
-        "%m/%d/%Y %I:%M %P",
-        "%Y/%m/%d %H:%M:%S",
-        "%Y/%m/%d %H:%M",
-        "%Y/%m/%d %H:%M:%S.%f",
-        "%Y/%m/%d %I:%M:%S %P",
-        "%Y/%m/%d %I:%M %P",
-        "%Y-%m-%d %H:%M:%S %Z",
-        "%Y-%m-%d %H:%M:%S.%f %Z",
-        "%B %d %Y %H:%M:%S %Z",
-        "%B %d %Y %H:%M %Z",
-        "%B %d %Y %I:%M:%S %P %Z",
-        "%B %d %Y %I:%M %P %Z",
-        "rfc2822",
-        "rfc3339",
-        "%Y-%m-%d",
-        "%Y-%b-%d",
-        "%B %d %Y %H:%M",
-        "%B %d %y",
-        "%B %d %Y",
-        "%d %B %y",
-        "%d %B %Y",
-        "%m/%d/%y",
-        "%m/%d/%Y",
-        "%Y/%m/%d",
-        "%m.%d.%Y",
-        "%Y.%m.%d",
-        "%y%m%d %H:%M:%S");
-
-
-    pub static ref PARQUET_ALLOWED_DEFAULT: Vec<&'static str> =
-    vec!(
-        "rfc3339",
-        "%Y-%m-%d %H:%M:%S%.f%:z",
-        "%Y-%m-%dT%H:%M:%S%.f",
-        "%Y-%m-%dT%H:%M:%S",
-        "%Y-%m-%d %H:%M:%S%.f",
-        "%Y-%m-%d %H:%M:%S"
-        );
-
-
-    // pub static ref PARQUET_ALLOWED_FORMAT: Vec<&'static str> =
-    // vec!(
-    //     //"%Y-%m-%d %H:%M:%S",
-    //     "%Y-%m-%d %H:%M",
-    //     "%Y-%m-%d %I:%M:%S %p",
-    //     "%Y-%m-%d %I:%M %p",
-    //     "%Y %b %d %H:%M:%S",
-    //     "%B %d %Y %H:%M:%S",
-    //     "%B %d %Y %I:%M:%S %p",
-    //     "%B %d %Y %I:%M %p",
-    //     "%Y %b %d at %I:%M %p",
-    //     "%d %B %Y %H:%M:%p",
-    //     "%d %B %Y %H:%M",
-    //     "%d %B %Y %I:%M:%S %p",
-    //     "%d %B %Y %I:%M %p",
-    //     "%B %d %Y %H:%M",
-    //     "%m/%d/%y %H:%M:%S",
-    //     "%m/%d/%y %H:%M",
-    //     "%m/%d/%y %I:%M:%S %p",
-    //     "%m/%d/%y %I:%M %p",
-    //     "%m/%d/%Y %H:%M:%S",
-    //     "%m/%d/%Y %H:%M",
-    //     "%m/%d/%Y %I:%M:%S %p",
-    //     "%m/%d/%Y %I:%M %p",
-    //     "%d/%m/%y %H:%M:%S",
-    //     "%d/%m/%y %H:%M",
-    //     "%d/%m/%y %I:%M:%S %p",
-    //     "%d/%m/%y %I:%M %p",
-    //     "%d/%m/%Y %H:%M:%S",
-    //     "%d/%m/%Y %H:%M",
-    //     "%d/%m/%Y %I:%M:%S %p",
-    //     "%d/%m/%Y %I:%M %p",
-    //     "%Y/%m/%d %H:%M:%S",
-    //     "%Y/%m/%d %H:%M",
-    //     "%Y/%m/%d %I:%M:%S %p",
-    //     "%Y/%m/%d %I:%M %p",
-    //     "%y%m%d %H:%M:%S",
-    // );
+/// A cleaned value bound with the storage class of its field type, so numbers
+/// and booleans are not stored as text. Integers too large for 64 bits stay text.
+fn sqlite_value(field_type: &str, value: Option<Cow<str>>) -> rusqlite::types::Value {
+    use rusqlite::types::Value as SqliteValue;
+    let Some(value) = value.filter(|value| !value.is_empty()) else {
+        return SqliteValue::Null;
+    };
+    match field_type {
+        "integer" => {
+            if let Ok(integer) = value.parse() {
+                return SqliteValue::Integer(integer);
+            }
+        }
+        "number" => {
+            if let Ok(number) = value.parse() {
+                return SqliteValue::Real(number);
+            }
+        }
+        "boolean" => {
+            if let Some(boolean) = parse_boolean(&value) {
+                return SqliteValue::Integer(boolean.into());
+            }
+        }
+        _ => {}
+    }
+    SqliteValue::Text(value.into_owned())
+}
 
+/// Numbers with more significant digits than an `f64` holds are bound as text.
+fn sqlite_field_type(field: &Value) -> &str {
+    match field["type"].as_str().unwrap_or("string") {
+        "number"
+            if field["precision"]
+                .as_u64()
+                .is_some_and(|precision| precision > 15) =>
+        {
+            "string"
+        }
+        field_type => field_type,
+    }
 }
 
 fn insert_sql_data(
     csv_reader: csv::Reader<impl std::io::Read>,
     conn: &mut rusqlite::Connection,
     resource: Value,
-) -> Result<(), Error> {
+    cleaner: &mut Cleaner,
+    on_conflict: Option<&str>,
+) -> Result<(u64, u64), Error> {
     let tx = conn.transaction().context(RusqliteSnafu {
         message: "Error making transaction: ",
     })?;
@@ -977,6 +1934,7 @@ fn insert_sql_data(
 
     let mut fields_len = 0;
     let mut fields = vec![];
+    let mut field_types = vec![];
 
     if let Some(fields_vec) = resource["schema"]["fields"].as_array() {
         fields_len = fields_vec.len();
@@ -984,6 +1942,7 @@ fn insert_sql_data(
             if let Some(field) = field_value["name"].as_str() {
                 fields.push(format!("[{field}]"))
             }
+            field_types.push(sqlite_field_type(field_value));
         }
     };
 
@@ -993,28 +1952,167 @@ fn insert_sql_data(
 
     question_marks.pop();
 
+    let on_conflict = on_conflict
+        .map(|clause| format!(" {clause}"))
+        .unwrap_or_default();
+    let mut rows = 0;
+    let mut changed = 0;
+
     {
         let mut statement = tx
             .prepare_cached(&format!(
-                "INSERT INTO [{table}]({fields}) VALUES ({question_marks})"
+                "INSERT INTO [{table}]({fields}) VALUES ({question_marks}){on_conflict}"
             ))
             .context(RusqliteSnafu {
                 message: "Error preparing sqlite statment: ",
             })?;
 
-        for row in csv_reader.into_deserialize() {
+        for (row_num, row) in csv_reader.into_deserialize().enumerate() {
             let this_row: Vec<String> = row.context(CSVSnafu { filename: table })?;
+            let this_row: Vec<rusqlite::types::Value> = this_row
+                .iter()
+                .enumerate()
+                .map(|(index, value)| {
+                    sqlite_value(
+                        field_types.get(index).copied().unwrap_or("string"),
+                        cleaner.clean(row_num + 1, index, value),
+                    )
+                })
+                .collect();
 
-            statement
-                .execute(rusqlite::params_from_iter(this_row.iter()))
+            changed += statement
+                .execute(rusqlite::params_from_iter(this_row))
                 .context(RusqliteSnafu {
                     message: "Error inserting data to sqlite: ",
-                })?;
+                })? as u64;
+            rows += 1;
         }
     }
     tx.commit().context(RusqliteSnafu {
         message: "Error commiting sqlite: ",
     })?;
+    Ok((rows, changed))
+}
+
+/// Whether a dump carries a resource's rows inline instead of loading its CSV
+/// file. `.import` and `\copy` read the file as it is, so values that need
+/// rewriting, such as dates like `31/01/2024`, always go inline.
+fn dump_inline(options: &Options, table: &str, cleaner: &Cleaner) -> bool {
+    if options.dump_inline {
+        return true;
+    }
+    let needs_rewrite = cleaner.needs_rewrite();
+    if needs_rewrite {
+        log::info!("{table} is dumped inline as its values need rewriting");
+    }
+    needs_rewrite
+}
+
+/// The `ENCODING` option `\copy` needs to read a file, or `None` for encodings
+/// Postgres can not read, such as UTF-16.
+fn copy_encoding(encoding: &'static Encoding) -> Option<String> {
+    if encoding == UTF_8 {
+        return Some(String::new());
+    }
+    let name = encoding.name();
+    let readable = name.starts_with("windows-")
+        || (name.starts_with("ISO-8859-") && name != "ISO-8859-8-I")
+        || [
+            "Shift_JIS",
+            "EUC-JP",
+            "EUC-KR",
+            "GBK",
+            "gb18030",
+            "Big5",
+            "KOI8-R",
+            "KOI8-U",
+        ]
+        .contains(&name);
+    readable.then(|| format!("ENCODING '{name}', "))
+}
+
+/// A value written as a SQLite literal. SQLite stores NaN as NULL and reads
+/// numbers too large for a double as infinity.
+fn sqlite_literal(value: rusqlite::types::Value) -> String {
+    use rusqlite::types::Value as SqliteValue;
+    match value {
+        SqliteValue::Null => "NULL".into(),
+        SqliteValue::Integer(integer) => integer.to_string(),
+        SqliteValue::Real(real) if real.is_nan() => "NULL".into(),
+        SqliteValue::Real(real) if real.is_infinite() => {
+            if real > 0.0 { "9e999" } else { "-9e999" }.into()
+        }
+        SqliteValue::Real(real) => format!("{real:?}"),
+        SqliteValue::Text(text) => format!("'{}'", text.replace('\'', "''")),
+        SqliteValue::Blob(blob) => {
+            let hex: String = blob.iter().map(|byte| format!("{byte:02x}")).collect();
+            format!("X'{hex}'")
+        }
+    }
+}
+
+/// Rows written as batched `INSERT` statements, so a dump can be replayed
+/// without the CSV files.
+fn write_sqlite_inserts(
+    csv_reader: csv::Reader<impl std::io::Read>,
+    writer: &mut impl Write,
+    resource: &Value,
+    cleaner: &mut Cleaner,
+    on_conflict: Option<&str>,
+    filename: &str,
+) -> Result<(), Error> {
+    const BATCH_SIZE: usize = 500;
+
+    let table = resource["title"]
+        .as_str()
+        .or(resource["name"].as_str())
+        .unwrap_or_default();
+
+    let mut fields = vec![];
+    let mut field_types = vec![];
+    if let Some(fields_vec) = resource["schema"]["fields"].as_array() {
+        for field_value in fields_vec {
+            if let Some(field) = field_value["name"].as_str() {
+                fields.push(format!("[{field}]"))
+            }
+            field_types.push(sqlite_field_type(field_value));
+        }
+    };
+    let insert = format!("INSERT INTO [{table}]({}) VALUES", fields.join(", "));
+    let on_conflict = on_conflict
+        .map(|clause| format!(" {clause}"))
+        .unwrap_or_default();
+
+    let mut batch: Vec<String> = Vec::with_capacity(BATCH_SIZE);
+    let write_batch = |batch: &mut Vec<String>, writer: &mut dyn Write| {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let result = writeln!(writer, "{insert}\n{}{on_conflict};", batch.join(",\n"));
+        batch.clear();
+        result.context(IoSnafu { filename })
+    };
+
+    writeln!(writer, "BEGIN;").context(IoSnafu { filename })?;
+    for (row_num, row) in csv_reader.into_deserialize().enumerate() {
+        let row: Vec<String> = row.context(CSVSnafu { filename: table })?;
+        let values: Vec<String> = row
+            .iter()
+            .enumerate()
+            .map(|(index, value)| {
+                sqlite_literal(sqlite_value(
+                    field_types.get(index).copied().unwrap_or("string"),
+                    cleaner.clean(row_num + 1, index, value),
+                ))
+            })
+            .collect();
+        batch.push(format!("({})", values.join(", ")));
+        if batch.len() == BATCH_SIZE {
+            write_batch(&mut batch, writer)?;
+        }
+    }
+    write_batch(&mut batch, writer)?;
+    writeln!(writer, "COMMIT;").context(IoSnafu { filename })?;
     Ok(())
 }
 
@@ -1044,6 +2142,25 @@ pub fn csvs_to_sqlite_with_options(
         .delimiter(options.delimiter)
         .quote(options.quote)
         .all_strings(options.all_strings)
+        .has_header(options.has_header)
+        .field_names(options.field_names.clone())
+        .encoding(options.encoding.clone())
+        .missing_values(options.missing_values.clone())
+        .decimal_char(options.decimal_char)
+        .group_char(options.group_char)
+        .bare_number(options.bare_number)
+        .date_order(options.date_order)
+        .datetime_formats(options.datetime_formats.clone())
+        .date_formats(options.date_formats.clone())
+        .time_formats(options.time_formats.clone())
+        .max_invalid_ratio(options.max_invalid_ratio)
+        .detect_keys(options.detect_keys)
+        .detect_foreign_keys(options.detect_foreign_keys)
+        .detect_enums(options.detect_enums)
+        .constraints(options.constraints)
+        .unique_constraints(options.unique_constraints)
+        .detect_formats(options.detect_formats)
+        .detectors(options.detectors.clone())
         .build();
     let datapackage = describe::describe_files(csvs, PathBuf::new(), &describe_options)
         .context(DescribeSnafu {})?;
@@ -1104,17 +2221,23 @@ pub fn datapackage_to_sqlite_with_options(
         writeln!(dump_writer, "{pragmas}").context(IoSnafu {
             filename: &options.dump_file,
         })?;
-        writeln!(dump_writer, ".mode csv").context(IoSnafu {
-            filename: &options.dump_file,
-        })?;
+        if !options.dump_inline {
+            writeln!(dump_writer, ".mode csv").context(IoSnafu {
+                filename: &options.dump_file,
+            })?;
+        }
     }
 
+    let mut rejects = vec![];
+    let mut upserts = vec![];
+    let mut sqlite_indexes = vec![];
+
     for table in ordered_tables {
         let resource = table_to_schema.get(&table).unwrap();
 
         let mut existing_columns: HashMap<String, String> = HashMap::new();
 
-        if options.drop || options.evolve || options.truncate {
+        if options.drop || options.evolve || options.truncate || options.upsert {
             if let Some(conn) = conn.as_mut() {
                 let mut fields_query = conn
                     .prepare("select name, type from pragma_table_info(?)")
@@ -1179,13 +2302,8 @@ pub fn datapackage_to_sqlite_with_options(
         );
 
         if create {
-            let resource_sqlite = render_sqlite_table(resource.clone())?;
+            let resource_sqlite = render_sqlite_table(resource.clone(), options.strict)?;
             if let Some(conn) = conn.as_mut() {
-                // `render_sqlite_table` can emit multiple statements (a CREATE
-                // TABLE plus CREATE INDEX statements for foreign keys), so use
-                // `execute_batch`. Since rusqlite 0.40, `execute` prepares the
-                // trailing statement eagerly and fails with "no such table"
-                // because the CREATE TABLE has not run yet.
                 conn.execute_batch(&resource_sqlite)
                     .context(RusqliteSnafu {
                         message: "Error making sqlite tables: ",
@@ -1197,7 +2315,13 @@ pub fn datapackage_to_sqlite_with_options(
                 })?;
             }
         } else if options.evolve {
-            let (add_columns, _alter_columns) = get_column_changes(resource, existing_columns);
+            let db_type = if options.strict {
+                strict_field_db_type
+            } else {
+                sqlite_field_db_type
+            };
+            let (add_columns, alter_columns) =
+                get_column_changes(resource, existing_columns.clone(), db_type);
             for (name, type_) in add_columns {
                 if let Some(conn) = conn.as_mut() {
                     conn.execute(&format!("ALTER TABLE {table} ADD [{name}] {type_}"), [])
@@ -1213,41 +2337,164 @@ pub fn datapackage_to_sqlite_with_options(
                     )?;
                 }
             }
+            let loaded_columns: Vec<&str> = resource["schema"]["fields"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|field| field["name"].as_str())
+                .collect();
+            if let Some(conn) = conn.as_mut()
+                && let Some(rebuild_sql) = rebuild_sqlite_table(
+                    conn,
+                    &table,
+                    &existing_columns,
+                    &alter_columns,
+                    &loaded_columns,
+                )?
+            {
+                // The loading pragmas turn the journal off, which would leave a
+                // half rebuilt table if the rebuild failed. Foreign keys must be
+                // off so dropping the old table does not touch referencing rows.
+                // Neither pragma can change inside a transaction.
+                let foreign_keys: bool = conn
+                    .query_row("PRAGMA foreign_keys", [], |row| row.get(0))
+                    .context(RusqliteSnafu {
+                        message: "Error altering sqlite tables: ",
+                    })?;
+                let rebuild_pragmas = "PRAGMA journal_mode = MEMORY;\nPRAGMA foreign_keys = OFF;\n";
+                let restore_pragmas = format!(
+                    "PRAGMA journal_mode = OFF;\nPRAGMA foreign_keys = {};\n",
+                    if foreign_keys { "ON" } else { "OFF" }
+                );
+                conn.execute_batch(rebuild_pragmas).context(RusqliteSnafu {
+                    message: "Error altering sqlite tables: ",
+                })?;
+                let transaction = conn.transaction().context(RusqliteSnafu {
+                    message: "Error altering sqlite tables: ",
+                })?;
+                transaction
+                    .execute_batch(&rebuild_sql)
+                    .context(RusqliteSnafu {
+                        message: "Error altering sqlite tables: ",
+                    })?;
+                transaction.commit().context(RusqliteSnafu {
+                    message: "Error altering sqlite tables: ",
+                })?;
+                conn.execute_batch(&restore_pragmas)
+                    .context(RusqliteSnafu {
+                        message: "Error altering sqlite tables: ",
+                    })?;
+                if let Some(dump_writer) = dump_writer.as_mut() {
+                    write!(
+                        dump_writer,
+                        "{rebuild_pragmas}BEGIN;\n{rebuild_sql}COMMIT;\n{restore_pragmas}"
+                    )
+                    .context(IoSnafu {
+                        filename: &options.dump_file,
+                    })?;
+                }
+            }
         }
 
         let resource_path = resource["path"].as_str().unwrap();
 
-        if let Some(conn) = conn.as_mut() {
-            let tempdir: Option<TempDir>;
-
-            let csv_path = if datapackage.ends_with(".zip") {
-                tempdir = Some(TempDir::new().context(IoSnafu {
-                    filename: &datapackage,
-                })?);
-                extract_csv_file(
-                    &datapackage.to_string(),
-                    &resource_path.to_owned(),
-                    &tempdir,
-                )?
-            } else {
-                get_path(&datapackage, resource_path, &options)?
+        let on_conflict = if options.upsert {
+            let primary_key = match conn.as_ref() {
+                Some(conn) => sqlite_primary_key(conn, &table)?,
+                None => resource_primary_key(resource),
             };
+            let clause = upsert_clause(resource, &primary_key, |name| format!("[{name}]"));
+            if clause.is_none() {
+                log::warn!("{table} has no primary key so its rows are appended");
+            }
+            clause
+        } else {
+            None
+        };
 
-            let csv_reader = get_csv_reader_builder(&options, resource)
-                .from_path(&csv_path)
-                .context(CSVSnafu {
-                    filename: csv_path.to_string_lossy().to_owned(),
-                })?;
-            insert_sql_data(csv_reader, conn, resource.clone())?;
+        let tempdir: Option<TempDir>;
 
-            if options.delete_input_csv {
-                std::fs::remove_file(&csv_path).context(IoSnafu {
-                    filename: csv_path.to_string_lossy(),
-                })?;
+        let csv_path = if datapackage.ends_with(".zip") {
+            tempdir = Some(TempDir::new().context(IoSnafu {
+                filename: &datapackage,
+            })?);
+            extract_csv_file(
+                &datapackage.to_string(),
+                &resource_path.to_owned(),
+                &tempdir,
+            )?
+        } else {
+            get_path(&datapackage, resource_path, &options)?
+        };
+
+        if let Some(conn) = conn.as_mut() {
+            let csv_reader = get_csv_reader_builder(&options, resource)
+                .from_reader(open_csv(&csv_path, &options, resource)?);
+            let mut cleaner = Cleaner::new(&options, resource);
+            let count_rows = |conn: &Connection| {
+                conn.query_row(&format!("SELECT count(*) FROM [{table}]"), [], |row| {
+                    row.get::<_, i64>(0).map(|count| count as u64)
+                })
+                .context(RusqliteSnafu {
+                    message: "Error counting rows: ",
+                })
+            };
+            let rows_before = if on_conflict.is_some() {
+                count_rows(conn)?
+            } else {
+                0
+            };
+            let (rows, changed) = insert_sql_data(
+                csv_reader,
+                conn,
+                resource.clone(),
+                &mut cleaner,
+                on_conflict.as_deref(),
+            )?;
+            if on_conflict.is_some() {
+                // `DO NOTHING` conflicts change no rows.
+                let inserted = count_rows(conn)? - rows_before;
+                upserts.push(Upsert {
+                    table: table.clone(),
+                    inserted,
+                    updated: changed - inserted,
+                    skipped: rows - changed,
+                });
             }
+            rejects.extend(cleaner.rejects);
         }
 
-        if let Some(dump_writer) = dump_writer.as_mut() {
+        let mut cleaner = Cleaner::new(&options, resource);
+        // `.import` only reads UTF-8, and STRICT columns reject the text it
+        // gives empty cells and booleans. Empty cells are loaded as `''`, which
+        // fails `CHECK` constraints, so fields with constraints need NULLs.
+        let utf8 = csv_encoding(&options, resource)? == UTF_8;
+        let constrained = resource["schema"]["fields"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .any(|field| {
+                field["constraints"]
+                    .as_object()
+                    .is_some_and(|c| !c.is_empty())
+            });
+        if let Some(dump_writer) = dump_writer.as_mut()
+            && (!utf8 || options.strict || constrained || dump_inline(&options, &table, &cleaner))
+        {
+            let csv_reader = get_csv_reader_builder(&options, resource)
+                .from_reader(open_csv(&csv_path, &options, resource)?);
+            write_sqlite_inserts(
+                csv_reader,
+                dump_writer,
+                resource,
+                &mut cleaner,
+                on_conflict.as_deref(),
+                &options.dump_file,
+            )?;
+            if conn.is_none() {
+                rejects.extend(cleaner.rejects);
+            }
+        } else if let Some(dump_writer) = dump_writer.as_mut() {
             let table_value = json!(table);
             let table_name = resource
                 .get("title")
@@ -1270,18 +2517,96 @@ pub fn datapackage_to_sqlite_with_options(
             writeln!(dump_writer, ".separator '{delimiter}'").context(IoSnafu {
                 filename: &options.dump_file,
             })?;
-            writeln!(
-                dump_writer,
-                ".import '{resource_path}' {table_name} --skip 1 "
-            )
-            .context(IoSnafu {
-                filename: &options.dump_file,
+            let skip = if csv_has_header(&options, resource) {
+                "--skip 1 "
+            } else {
+                ""
+            };
+
+            // Files are found from the datapackage, not where the dump is replayed.
+            let full_path = canonicalize(&csv_path).context(IoSnafu {
+                filename: csv_path.to_string_lossy(),
             })?;
-        }
-    }
+            let full_path = full_path.to_string_lossy();
+            // sqlite3 reads from a pipe when the file name starts with `|`.
+            let compression = compression::detect(&csv_path).context(IoSnafu {
+                filename: csv_path.to_string_lossy(),
+            })?;
+            let source = match compression {
+                Some(compression) => format!("|{} {full_path}", compression.command()),
+                None => full_path.to_string(),
+            };
 
-    Ok(())
-}
+            if let Some(on_conflict) = &on_conflict {
+                // `.import` can not upsert, so rows are imported into a staging
+                // table and inserted from there.
+                let fields = resource["schema"]["fields"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|field| field["name"].as_str())
+                    .map(|name| format!("[{name}]"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let staging = format!("_upsert_{table_name}");
+                writeln!(
+                    dump_writer,
+                    "CREATE TABLE [{staging}] AS SELECT {fields} FROM [{table_name}] WHERE false;\n\
+                     .import '{source}' {staging} {skip}\n\
+                     INSERT INTO [{table_name}]({fields}) SELECT {fields} FROM [{staging}] WHERE true {on_conflict};\n\
+                     DROP TABLE [{staging}];"
+                )
+                .context(IoSnafu {
+                    filename: &options.dump_file,
+                })?;
+            } else {
+                writeln!(dump_writer, ".import '{source}' {table_name} {skip}").context(
+                    IoSnafu {
+                        filename: &options.dump_file,
+                    },
+                )?;
+            }
+        }
+
+        if conn.is_some() && options.delete_input_csv {
+            std::fs::remove_file(&csv_path).context(IoSnafu {
+                filename: csv_path.to_string_lossy(),
+            })?;
+        }
+
+        let resource_name = resource["name"].as_str().unwrap_or(&table);
+        let extra_indexes = options
+            .indexes
+            .get(resource_name)
+            .or_else(|| options.indexes.get(&table))
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        sqlite_indexes.push(render_sqlite_indexes(resource.clone(), extra_indexes)?);
+    }
+
+    write_upserts(&options, &upserts)?;
+
+    // Indexes are cheaper to build once the data is in, and `ANALYZE` gives
+    // the query planner statistics for them.
+    let mut after_load = sqlite_indexes.concat();
+    after_load.push_str("ANALYZE;\n");
+
+    if let Some(conn) = conn.as_mut() {
+        conn.execute_batch(&after_load).context(RusqliteSnafu {
+            message: "Error making sqlite indexes: ",
+        })?;
+    }
+
+    if let Some(dump_writer) = dump_writer.as_mut() {
+        write!(dump_writer, "{after_load}").context(IoSnafu {
+            filename: &options.dump_file,
+        })?;
+    }
+
+    write_rejects(&options, &rejects)?;
+
+    Ok(())
+}
 
 fn get_table_info(
     datapackage: &str,
@@ -1343,7 +2668,7 @@ fn create_parquet(
     resource: Value,
     mut output_path: PathBuf,
     options: &Options,
-) -> Result<(), Error> {
+) -> Result<Vec<Reject>, Error> {
     ensure!(
         resource["name"].is_string(),
         DatapackageMergeSnafu {
@@ -1388,44 +2713,101 @@ fn create_parquet(
 
         let format_type = field["format"].as_str().unwrap_or("_");
 
+        // Decimals hold up to 38 digits, beyond that numbers are floats or text.
+        let precision = field["precision"]
+            .as_u64()
+            .and_then(|precision| u8::try_from(precision).ok())
+            .filter(|precision| *precision <= 38);
+        let scale = field["scale"]
+            .as_u64()
+            .and_then(|scale| i8::try_from(scale).ok());
+
         let field = match (field_type, format_type) {
-            ("number", _) => Field::new(name, DataType::Float64, true),
-            ("integer", _) => Field::new(name, DataType::Int64, true),
-            ("boolean", _) => Field::new(name, DataType::Boolean, true),
-            ("datetime", f) => {
-                if PARQUET_ALLOWED_DEFAULT.contains(&f) {
-                    Field::new(name, DataType::Timestamp(TimeUnit::Nanosecond, None), true)
-                } else {
+            ("number", _) => match (precision, scale) {
+                (Some(precision), Some(scale)) => {
+                    Field::new(name, DataType::Decimal128(precision, scale), true)
+                }
+                _ => Field::new(name, DataType::Float64, true),
+            },
+            ("integer", _) => match (field["bitWidth"].as_u64(), precision) {
+                (Some(16 | 32), _) => Field::new(name, DataType::Int32, true),
+                (None, Some(precision)) => {
+                    Field::new(name, DataType::Decimal128(precision, 0), true)
+                }
+                (None, None) if field["precision"].is_u64() => {
                     Field::new(name, DataType::Utf8, true)
                 }
+                _ => Field::new(name, DataType::Int64, true),
+            },
+            ("boolean", _) => Field::new(name, DataType::Boolean, true),
+            // Values are written in ISO 8601 by the cleaner.
+            ("date", _) => Field::new(name, DataType::Date32, true),
+            ("time", _) => Field::new(name, DataType::Time64(TimeUnit::Microsecond), true),
+            ("datetime", _) => {
+                Field::new(name, DataType::Timestamp(TimeUnit::Nanosecond, None), true)
             }
+            // Offsets are applied, so values are stored as UTC instants.
+            ("datetime_tz", _) => Field::new(
+                name,
+                DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into())),
+                true,
+            ),
             _ => Field::new(name, DataType::Utf8, true),
         };
         arrow_fields.push(field);
     }
 
-    let mut delimiter = options.delimiter.unwrap_or(b',');
-    if let Some(dialect_delimiter) = resource["dialect"]["delimiter"].as_str() {
-        if dialect_delimiter.as_bytes().len() == 1 {
-            delimiter = *dialect_delimiter.as_bytes().first().unwrap()
-        }
+    let (delimiter, quote, _) = csv_dialect(options, &resource);
+
+    // Arrow only parses plain values, so anything else is cleaned into a
+    // temporary copy first.
+    let mut cleaner = Cleaner::new(options, &resource);
+    let file: Box<dyn std::io::Read> = if cleaner.needs_rewrite() {
+        let mut clean_file = tempfile::tempfile().context(IoSnafu {
+            filename: file.to_string_lossy(),
+        })?;
+        write_clean_csv(
+            &file,
+            std::io::BufWriter::new(&mut clean_file),
+            &mut cleaner,
+            options,
+            &resource,
+            csv::QuoteStyle::Necessary,
+        )?;
+        std::io::Seek::rewind(&mut clean_file).context(IoSnafu {
+            filename: file.to_string_lossy(),
+        })?;
+        Box::new(clean_file)
+    } else {
+        Box::new(open_csv(&file, options, &resource)?)
     };
 
-    let file = File::open(file.clone()).context(IoSnafu {
-        filename: file.to_string_lossy().to_string(),
-    })?;
+    let mut arrow_reader_builder =
+        ArrowReaderBuilder::new(std::sync::Arc::new(Schema::new(arrow_fields)))
+            .with_header(csv_has_header(options, &resource))
+            .with_delimiter(delimiter)
+            .with_quote(quote)
+            .with_batch_size(1024);
+
+    if let Some(escape) = csv_escape(options, &resource) {
+        arrow_reader_builder = arrow_reader_builder.with_escape(escape);
+    }
 
-    let arrow_csv_reader = ArrowReaderBuilder::new(std::sync::Arc::new(Schema::new(arrow_fields)))
-        .with_header(true)
-        .with_delimiter(delimiter)
-        .with_batch_size(1024)
-        .build(file)
-        .context(ArrowSnafu {})?;
+    let arrow_csv_reader = arrow_reader_builder.build(file).context(ArrowSnafu {})?;
 
-    let props = WriterProperties::builder()
+    let mut props = WriterProperties::builder()
         .set_dictionary_enabled(false)
         .set_compression(Compression::SNAPPY);
 
+    // Columns with only a few allowed values are dictionary encoded.
+    if let Some(fields) = resource["schema"]["fields"].as_array() {
+        for (field, values) in fields.iter().zip(enum_values(&resource)) {
+            if let (Some(name), Some(_)) = (field["name"].as_str(), values) {
+                props = props.set_column_dictionary_enabled(ColumnPath::from(name), true);
+            }
+        }
+    }
+
     let output = File::create(&output_path).context(IoSnafu {
         filename: output_path.to_string_lossy(),
     })?;
@@ -1444,7 +2826,7 @@ fn create_parquet(
     }
     .context(ParquetSnafu {})?;
 
-    Ok(())
+    Ok(cleaner.rejects)
 }
 
 #[cfg(feature = "parquet")]
@@ -1475,6 +2857,25 @@ pub fn csvs_to_parquet_with_options(
         .delimiter(options.delimiter)
         .quote(options.quote)
         .all_strings(options.all_strings)
+        .has_header(options.has_header)
+        .field_names(options.field_names.clone())
+        .encoding(options.encoding.clone())
+        .missing_values(options.missing_values.clone())
+        .decimal_char(options.decimal_char)
+        .group_char(options.group_char)
+        .bare_number(options.bare_number)
+        .date_order(options.date_order)
+        .datetime_formats(options.datetime_formats.clone())
+        .date_formats(options.date_formats.clone())
+        .time_formats(options.time_formats.clone())
+        .max_invalid_ratio(options.max_invalid_ratio)
+        .detect_keys(options.detect_keys)
+        .detect_foreign_keys(options.detect_foreign_keys)
+        .detect_enums(options.detect_enums)
+        .constraints(options.constraints)
+        .unique_constraints(options.unique_constraints)
+        .detect_formats(options.detect_formats)
+        .detectors(options.detectors.clone())
         .build();
     let datapackage = describe::describe_files(csvs, PathBuf::new(), &describe_options)
         .context(DescribeSnafu {})?;
@@ -1517,6 +2918,8 @@ pub fn datapackage_to_parquet_with_options(
         }
     );
 
+    let mut rejects = vec![];
+
     for resource in resources_option.unwrap() {
         let resource_path = resource["path"].as_str().unwrap();
 
@@ -1535,12 +2938,12 @@ pub fn datapackage_to_parquet_with_options(
             get_path(&datapackage, resource_path, &options)?
         };
 
-        create_parquet(
+        rejects.extend(create_parquet(
             csv_path.clone(),
             resource.clone(),
             output_path.clone(),
             &options,
-        )?;
+        )?);
 
         if options.delete_input_csv {
             std::fs::remove_file(&csv_path).context(IoSnafu {
@@ -1549,6 +2952,8 @@ pub fn datapackage_to_parquet_with_options(
         }
     }
 
+    write_rejects(&options, &rejects)?;
+
     Ok(())
 }
 
@@ -1585,22 +2990,68 @@ pub fn truncate_xlsx_title(mut title: String, seperator: &str) -> String {
     new_parts.join(seperator)
 }
 
+/// A date, datetime or time written in ISO 8601 by the cleaner as an Excel
+/// date. Datetimes with an offset and dates before 1900 stay as text.
+fn excel_datetime(field_type: &str, value: &str) -> Option<ExcelDateTime> {
+    let date = |date: NaiveDate| {
+        ExcelDateTime::from_ymd(
+            date.year().try_into().ok()?,
+            date.month() as u8,
+            date.day() as u8,
+        )
+        .ok()
+    };
+    let seconds = |time: NaiveTime| f64::from(time.second()) + f64::from(time.nanosecond()) / 1e9;
+    match field_type {
+        "date" => date(NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?),
+        "time" => {
+            let time = NaiveTime::parse_from_str(value, "%H:%M:%S%.f").ok()?;
+            ExcelDateTime::from_hms(time.hour() as u16, time.minute() as u8, seconds(time)).ok()
+        }
+        "datetime" | "datetime_tz" => {
+            let datetime = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f").ok()?;
+            let time = datetime.time();
+            date(datetime.date())?
+                .and_hms(time.hour() as u16, time.minute() as u8, seconds(time))
+                .ok()
+        }
+        _ => None,
+    }
+}
+
 fn create_sheet(
     csv_reader: csv::Reader<impl std::io::Read>,
     resource: Value,
     workbook: &mut Workbook,
     options: &Options,
-) -> Result<(), Error> {
+) -> Result<Vec<Reject>, Error> {
     let bold_format = Format::new().set_bold();
 
     let base_format = Format::new();
 
+    let date_formats = HashMap::from([
+        ("date", Format::new().set_num_format("yyyy-mm-dd")),
+        ("time", Format::new().set_num_format("hh:mm:ss")),
+        (
+            "datetime",
+            Format::new().set_num_format("yyyy-mm-dd hh:mm:ss"),
+        ),
+        (
+            "datetime_tz",
+            Format::new().set_num_format("yyyy-mm-dd hh:mm:ss"),
+        ),
+    ]);
+
     let mut field_types = vec![];
+    let mut field_names = vec![];
+    let mut field_formats = vec![];
     if let Some(fields_vec) = resource["schema"]["fields"].as_array() {
         for value in fields_vec {
             if let Some(field_type) = value["type"].as_str() {
                 field_types.push(field_type.to_owned());
             }
+            field_names.push(value["name"].as_str().unwrap_or_default().to_owned());
+            field_formats.push(value["format"].as_str().unwrap_or_default().to_owned());
         }
     };
 
@@ -1611,6 +3062,10 @@ fn create_sheet(
         }
     );
 
+    let mut cleaner = Cleaner::new(options, &resource);
+    let enum_values = enum_values(&resource);
+    let mut last_row = 0;
+
     let mut title = String::new();
 
     if let Some(t) = resource["name"].as_str() {
@@ -1635,8 +3090,27 @@ fn create_sheet(
     let worksheet = workbook.add_worksheet_with_low_memory();
     worksheet.set_name(&new_title).context(XLSXSnafu {})?;
 
+    // CSVs without a header row get one written from the field names.
+    let row_offset = if csv_has_header(options, &resource) {
+        0
+    } else {
+        for (col_index, name) in field_names.iter().enumerate() {
+            worksheet
+                .write_with_format(
+                    0,
+                    col_index.try_into().expect("already checked field count"),
+                    name,
+                    &bold_format,
+                )
+                .context(XLSXSnafu {})?;
+        }
+        1
+    };
+
     for (row_num, row) in csv_reader.into_records().enumerate() {
+        let row_num = row_num + row_offset;
         let this_row = row.context(CSVSnafu { filename: &title })?;
+        last_row = row_num;
 
         let mut format = &base_format;
 
@@ -1658,6 +3132,16 @@ fn create_sheet(
         }
 
         for (col_index, value) in this_row.iter().enumerate() {
+            // Missing and rejected values are left as empty cells.
+            let value = if row_num == 0 {
+                Cow::Borrowed(value)
+            } else {
+                match cleaner.clean(row_num, col_index, value) {
+                    Some(value) => value,
+                    None => continue,
+                }
+            };
+
             let mut cell = value.to_string();
 
             if ["number", "integer"].contains(&field_types[col_index].as_str()) {
@@ -1679,6 +3163,21 @@ fn create_sheet(
                 }
             }
 
+            if row_num > 0
+                && let Some(date_format) = date_formats.get(field_types[col_index].as_str())
+                && let Some(datetime) = excel_datetime(&field_types[col_index], &value)
+            {
+                worksheet
+                    .write_datetime_with_format(
+                        row_num.try_into().unwrap(),
+                        col_index.try_into().unwrap(),
+                        &datetime,
+                        date_format,
+                    )
+                    .context(XLSXSnafu {})?;
+                continue;
+            }
+
             if INVALID_REGEX.is_match(&cell) {
                 cell = INVALID_REGEX.replace_all(&cell, "").to_string();
             }
@@ -1694,6 +3193,21 @@ fn create_sheet(
                 cell.truncate(index)
             }
 
+            // Links Excel can not take, such as unknown schemes or ones over
+            // 2079 characters, are written as text.
+            if row_num > 0
+                && field_formats[col_index] == "uri"
+                && worksheet
+                    .write_url(
+                        row_num.try_into().expect("already tested length of string"),
+                        col_index.try_into().expect("already checked field count"),
+                        cell.as_str(),
+                    )
+                    .is_ok()
+            {
+                continue;
+            }
+
             worksheet
                 .write_with_format(
                     row_num.try_into().expect("already tested length of string"),
@@ -1704,7 +3218,28 @@ fn create_sheet(
                 .context(XLSXSnafu {})?;
         }
     }
-    Ok(())
+
+    // Columns with only a few allowed values get a drop-down list, when it
+    // fits in the 255 characters Excel allows.
+    for (col_index, values) in enum_values.iter().enumerate() {
+        if let Some(values) = values
+            && last_row > 0
+            && let Ok(validation) = DataValidation::new().allow_list_strings(values)
+        {
+            worksheet
+                .add_data_validation(
+                    1,
+                    col_index.try_into().expect("already checked field count"),
+                    last_row
+                        .try_into()
+                        .expect("already tested length of string"),
+                    col_index.try_into().expect("already checked field count"),
+                    &validation,
+                )
+                .context(XLSXSnafu {})?;
+        }
+    }
+    Ok(cleaner.rejects)
 }
 
 pub fn csvs_to_xlsx(xlsx_path: String, csvs: Vec<PathBuf>) -> Result<Value, Error> {
@@ -1733,6 +3268,25 @@ pub fn csvs_to_xlsx_with_options(
         .delimiter(options.delimiter)
         .quote(options.quote)
         .all_strings(options.all_strings)
+        .has_header(options.has_header)
+        .field_names(options.field_names.clone())
+        .encoding(options.encoding.clone())
+        .missing_values(options.missing_values.clone())
+        .decimal_char(options.decimal_char)
+        .group_char(options.group_char)
+        .bare_number(options.bare_number)
+        .date_order(options.date_order)
+        .datetime_formats(options.datetime_formats.clone())
+        .date_formats(options.date_formats.clone())
+        .time_formats(options.time_formats.clone())
+        .max_invalid_ratio(options.max_invalid_ratio)
+        .detect_keys(options.detect_keys)
+        .detect_foreign_keys(options.detect_foreign_keys)
+        .detect_enums(options.detect_enums)
+        .constraints(options.constraints)
+        .unique_constraints(options.unique_constraints)
+        .detect_formats(options.detect_formats)
+        .detectors(options.detectors.clone())
         .build();
     let datapackage = describe::describe_files(csvs, PathBuf::new(), &describe_options)
         .context(DescribeSnafu {})?;
@@ -1775,6 +3329,8 @@ pub fn datapackage_to_xlsx_with_options(
     let mut workbook = Workbook::new();
     workbook.set_tempdir(pathbuf).context(XLSXSnafu {})?;
 
+    let mut rejects = vec![];
+
     for resource in resources_option.unwrap() {
         let resource_path = resource["path"].as_str().unwrap();
 
@@ -1795,20 +3351,23 @@ pub fn datapackage_to_xlsx_with_options(
 
         let csv_reader = get_csv_reader_builder(&options, resource)
             .has_headers(false)
-            .from_path(&csv_path)
-            .context(CSVSnafu {
-                filename: csv_path.to_string_lossy().to_string(),
-            })?;
+            .from_reader(open_csv(&csv_path, &options, resource)?);
 
         if options.delete_input_csv {
             std::fs::remove_file(&csv_path).context(IoSnafu {
                 filename: csv_path.to_string_lossy(),
             })?;
         }
-        create_sheet(csv_reader, resource.clone(), &mut workbook, &options)?;
+        rejects.extend(create_sheet(
+            csv_reader,
+            resource.clone(),
+            &mut workbook,
+            &options,
+        )?);
     }
 
     workbook.save(&xlsx_path).context(XLSXSnafu {})?;
+    write_rejects(&options, &rejects)?;
 
     Ok(())
 }
@@ -1839,6 +3398,25 @@ pub fn csvs_to_postgres_with_options(
         .delimiter(options.delimiter)
         .quote(options.quote)
         .all_strings(options.all_strings)
+        .has_header(options.has_header)
+        .field_names(options.field_names.clone())
+        .encoding(options.encoding.clone())
+        .missing_values(options.missing_values.clone())
+        .decimal_char(options.decimal_char)
+        .group_char(options.group_char)
+        .bare_number(options.bare_number)
+        .date_order(options.date_order)
+        .datetime_formats(options.datetime_formats.clone())
+        .date_formats(options.date_formats.clone())
+        .time_formats(options.time_formats.clone())
+        .max_invalid_ratio(options.max_invalid_ratio)
+        .detect_keys(options.detect_keys)
+        .detect_foreign_keys(options.detect_foreign_keys)
+        .detect_enums(options.detect_enums)
+        .constraints(options.constraints)
+        .unique_constraints(options.unique_constraints)
+        .detect_formats(options.detect_formats)
+        .detectors(options.detectors.clone())
         .build();
     let datapackage = describe::describe_files(csvs, PathBuf::new(), &describe_options)
         .context(DescribeSnafu {})?;
@@ -1859,7 +3437,7 @@ pub fn datapackage_to_postgres(postgres_url: String, datapackage: String) -> Res
 pub fn datapackage_to_postgres_with_options(
     postgres_url: String,
     datapackage: String,
-    options: Options,
+    mut options: Options,
 ) -> Result<(), Error> {
     let (table_to_schema, ordered_tables) = get_table_info(&datapackage, &options)?;
 
@@ -1882,7 +3460,7 @@ pub fn datapackage_to_postgres_with_options(
     }
 
     let mut client = if !postgres_url.is_empty() {
-        Some(Client::connect(&conf, NoTls).context(PostgresSnafu {})?)
+        Some(connect_postgres(&conf, &options)?)
     } else {
         None
     };
@@ -1901,267 +3479,732 @@ pub fn datapackage_to_postgres_with_options(
         None
     };
 
-    for table in ordered_tables {
-        let resource = table_to_schema.get(&table).unwrap();
+    let mut rejects = vec![];
+    let mut upserts = vec![];
 
+    // With `swap_schema` the tables are loaded into a fresh schema which
+    // replaces the live one once everything has loaded, so readers never see
+    // a partial load. The fresh schema only holds the loaded rows, so options
+    // that keep live rows can not be used with it. The old schema is dropped
+    // with `CASCADE`, which also drops views in other schemas that use it.
+    let live_schema = options.schema.clone();
+    if options.swap_schema {
         ensure!(
-            resource["path"].is_string(),
-            DatapackageMergeSnafu {
-                message: "Datapackages resources need a `path`"
+            !live_schema.is_empty(),
+            DatapackageConvertSnafu {
+                message: "`swap_schema` needs a `schema` to swap"
             }
         );
+        ensure!(
+            !(options.upsert || options.evolve || options.truncate),
+            DatapackageConvertSnafu {
+                message: "`swap_schema` replaces every table so can not be used with `upsert`, `evolve` or `truncate`"
+            }
+        );
+        options.schema = format!("{live_schema}_load");
+    }
+    let transaction = options.transaction || options.swap_schema;
 
-        let resource_path = resource["path"].as_str().unwrap();
-
-        let mut resource_postgres = render_postgres_table(resource.clone())?;
-
-        let mut schema_table = format!("\"{table}\"");
-
-        if !options.schema.is_empty() {
-            resource_postgres = format!(
-                r#"
-CREATE SCHEMA IF NOT EXISTS "{schema}";
-set search_path = "{schema}";
-{resource_postgres};
-"#,
-                schema = options.schema
-            );
-            schema_table = format!("\"{schema}\".\"{table}\"", schema = options.schema);
+    let mut begin = String::new();
+    if transaction {
+        writeln!(begin, "BEGIN;").unwrap();
+    }
+    if options.swap_schema {
+        writeln!(
+            begin,
+            "DROP SCHEMA IF EXISTS \"{schema}\" CASCADE;",
+            schema = options.schema
+        )
+        .unwrap();
+    }
+    if !begin.is_empty() {
+        if let Some(dump_writer) = dump_writer.as_mut() {
+            write!(dump_writer, "{begin}").context(IoSnafu {
+                filename: &options.dump_file,
+            })?;
         }
-
-        let mut create = true;
-
         if let Some(client) = client.as_mut() {
-            let result = client
-                .query_one("SELECT to_regclass($1)::TEXT", &[&schema_table])
-                .context(PostgresSnafu {})?;
-            let exists: Option<String> = result.get(0);
-            create = exists.is_none();
+            client.batch_execute(&begin).context(PostgresSnafu {})?;
         }
+    }
 
-        let mut drop = options.drop;
-
-        let mut existing_columns = None;
+    let load = || -> Result<(), Error> {
+        for table in ordered_tables {
+            let resource = table_to_schema.get(&table).unwrap();
 
-        if !create && options.evolve {
-            if let Some(client) = client.as_mut() {
-                let result = client
-                    .query_opt(&format!("SELECT * FROM {schema_table} limit 1"), &[])
-                    .context(PostgresSnafu {})?;
-                if result.is_none() {
-                    drop = true
-                }
-                if let Some(row) = result {
-                    let mut columns = HashMap::new();
-                    for column in row.columns() {
-                        columns.insert(column.name().to_owned(), column.type_().to_string());
-                    }
-                    existing_columns = Some(columns)
+            ensure!(
+                resource["path"].is_string(),
+                DatapackageMergeSnafu {
+                    message: "Datapackages resources need a `path`"
                 }
-            }
-        }
+            );
 
-        if options.truncate && !create {
-            if let Some(client) = client.as_mut() {
-                client
-                    .batch_execute(&format!("TRUNCATE TABLE {schema_table} CASCADE;"))
-                    .context(PostgresSnafu {})?;
-            }
-        }
+            let resource_path = resource["path"].as_str().unwrap();
+
+            let mut resource_postgres = render_postgres_table(resource.clone())?;
+
+            let mut schema_table = format!("\"{table}\"");
 
-        if drop && !create {
-            create = true;
-            let mut drop_statement = String::new();
             if !options.schema.is_empty() {
-                write!(
-                    drop_statement,
-                    "set search_path = \"{schema}\";",
+                resource_postgres = format!(
+                    r#"
+    CREATE SCHEMA IF NOT EXISTS "{schema}";
+    set search_path = "{schema}";
+    {resource_postgres};
+    "#,
                     schema = options.schema
-                )
-                .unwrap();
-            }
-            write!(drop_statement, "DROP TABLE IF EXISTS \"{table}\" CASCADE;").unwrap();
-            if let Some(client) = client.as_mut() {
-                if let Some(dump_writer) = dump_writer.as_mut() {
-                    writeln!(dump_writer, "{drop_statement}").context(IoSnafu {
-                        filename: &options.dump_file,
-                    })?;
-                }
-                client
-                    .batch_execute(&drop_statement)
-                    .context(PostgresSnafu {})?
+                );
+                schema_table = format!("\"{schema}\".\"{table}\"", schema = options.schema);
             }
-        }
 
-        if create {
-            if let Some(dump_writer) = dump_writer.as_mut() {
-                writeln!(dump_writer, "{resource_postgres}").context(IoSnafu {
-                    filename: &options.dump_file,
-                })?;
-            }
+            let mut create = true;
+
             if let Some(client) = client.as_mut() {
-                client
-                    .batch_execute(&resource_postgres)
+                let result = client
+                    .query_one("SELECT to_regclass($1)::TEXT", &[&schema_table])
                     .context(PostgresSnafu {})?;
+                let exists: Option<String> = result.get(0);
+                create = exists.is_none();
             }
-        }
 
-        let mut columns = vec![];
-        if let Some(fields) = resource["schema"]["fields"].as_array() {
-            for field in fields {
-                if let Some(name) = field["name"].as_str() {
-                    columns.push(format!("\"{name}\""));
-                }
-            }
-        }
-        let mut all_columns = columns.join(", ");
+            let mut drop = options.drop;
 
-        if INVALID_REGEX.is_match(&all_columns) {
-            all_columns = INVALID_REGEX.replace_all(&all_columns, "").to_string();
-        }
+            let mut existing_columns = None;
 
-        if let Some(existing_columns) = existing_columns {
-            let (add_columns, alter_columns) = get_column_changes(resource, existing_columns);
-            for (name, type_) in add_columns {
+            if !create && options.evolve {
                 if let Some(client) = client.as_mut() {
-                    if let Some(dump_writer) = dump_writer.as_mut() {
-                        writeln!(
-                            dump_writer,
-                            "ALTER TABLE {schema_table} ADD COLUMN \"{name}\" {type_}"
-                        )
-                        .context(IoSnafu {
-                            filename: &options.dump_file,
-                        })?;
+                    let result = client
+                        .query_opt(&format!("SELECT * FROM {schema_table} limit 1"), &[])
+                        .context(PostgresSnafu {})?;
+                    if result.is_none() {
+                        drop = true
+                    }
+                    if let Some(row) = result {
+                        let mut columns = HashMap::new();
+                        for column in row.columns() {
+                            columns.insert(column.name().to_owned(), column.type_().to_string());
+                        }
+                        existing_columns = Some(columns)
                     }
+                }
+            }
+
+            if options.truncate && !create {
+                if let Some(client) = client.as_mut() {
                     client
-                        .batch_execute(&format!(
-                            "ALTER TABLE {schema_table} ADD COLUMN \"{name}\" {type_}"
-                        ))
+                        .batch_execute(&format!("TRUNCATE TABLE {schema_table} CASCADE;"))
                         .context(PostgresSnafu {})?;
                 }
             }
 
-            for name in alter_columns {
+            if drop && !create {
+                create = true;
+                let mut drop_statement = String::new();
+                if !options.schema.is_empty() {
+                    write!(
+                        drop_statement,
+                        "set search_path = \"{schema}\";",
+                        schema = options.schema
+                    )
+                    .unwrap();
+                }
+                write!(drop_statement, "DROP TABLE IF EXISTS \"{table}\" CASCADE;").unwrap();
                 if let Some(client) = client.as_mut() {
                     if let Some(dump_writer) = dump_writer.as_mut() {
-                        writeln!(
-                            dump_writer,
-                            "ALTER TABLE {schema_table} ALTER COLUMN \"{name}\" TYPE TEXT"
-                        )
-                        .context(IoSnafu {
+                        writeln!(dump_writer, "{drop_statement}").context(IoSnafu {
                             filename: &options.dump_file,
                         })?;
                     }
                     client
-                        .batch_execute(&format!(
-                            "ALTER TABLE {schema_table} ALTER COLUMN \"{name}\" TYPE TEXT"
-                        ))
+                        .batch_execute(&drop_statement)
+                        .context(PostgresSnafu {})?
+                }
+            }
+
+            if create {
+                if let Some(dump_writer) = dump_writer.as_mut() {
+                    writeln!(dump_writer, "{resource_postgres}").context(IoSnafu {
+                        filename: &options.dump_file,
+                    })?;
+                }
+                if let Some(client) = client.as_mut() {
+                    client
+                        .batch_execute(&resource_postgres)
                         .context(PostgresSnafu {})?;
                 }
             }
-        }
 
-        let mut delimiter_u8 = options.delimiter.unwrap_or(b',');
+            let mut columns = vec![];
+            if let Some(fields) = resource["schema"]["fields"].as_array() {
+                for field in fields {
+                    if let Some(name) = field["name"].as_str() {
+                        columns.push(format!("\"{name}\""));
+                    }
+                }
+            }
+            let mut all_columns = columns.join(", ");
 
-        if let Some(dialect_delimiter) = resource["dialect"]["delimiter"].as_str() {
-            if dialect_delimiter.as_bytes().len() == 1 {
-                delimiter_u8 = *dialect_delimiter.as_bytes().first().unwrap()
+            if INVALID_REGEX.is_match(&all_columns) {
+                all_columns = INVALID_REGEX.replace_all(&all_columns, "").to_string();
             }
-        };
 
-        let delimiter = std::str::from_utf8(&[delimiter_u8])
-            .context(DelimeiterSnafu {})?
-            .to_owned();
+            if let Some(existing_columns) = existing_columns {
+                let loaded_columns: Vec<String> = resource["schema"]["fields"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|field| field["name"].as_str())
+                    .filter(|name| existing_columns.contains_key(*name))
+                    .map(str::to_owned)
+                    .collect();
+                let (add_columns, alter_columns) =
+                    get_column_changes(resource, existing_columns, postgres_field_type);
+                // New values would fail the enum `CHECK` of a loaded column, and
+                // the `CHECK` constraints of an altered column were written for
+                // its old type.
+                if let Some(client) = client.as_mut() {
+                    for name in &loaded_columns {
+                        let altered = alter_columns.iter().any(|(altered, _)| altered == name);
+                        for statement in
+                            postgres_drop_checks(client, &schema_table, name, !altered)?
+                        {
+                            if let Some(dump_writer) = dump_writer.as_mut() {
+                                writeln!(dump_writer, "{statement};").context(IoSnafu {
+                                    filename: &options.dump_file,
+                                })?;
+                            }
+                            client.batch_execute(&statement).context(PostgresSnafu {})?;
+                        }
+                    }
+                }
+                for (name, type_) in add_columns {
+                    if let Some(client) = client.as_mut() {
+                        if let Some(dump_writer) = dump_writer.as_mut() {
+                            writeln!(
+                                dump_writer,
+                                "ALTER TABLE {schema_table} ADD COLUMN \"{name}\" {type_}"
+                            )
+                            .context(IoSnafu {
+                                filename: &options.dump_file,
+                            })?;
+                        }
+                        client
+                            .batch_execute(&format!(
+                                "ALTER TABLE {schema_table} ADD COLUMN \"{name}\" {type_}"
+                            ))
+                            .context(PostgresSnafu {})?;
+                    }
+                }
 
-        let mut quote_u8 = options.quote.unwrap_or(b'"');
-        if let Some(dialect_quote) = resource["dialect"]["quote"].as_str() {
-            if dialect_quote.as_bytes().len() == 1 {
-                quote_u8 = *dialect_quote.as_bytes().first().unwrap()
+                for (name, type_) in alter_columns {
+                    if let Some(client) = client.as_mut() {
+                        if let Some(dump_writer) = dump_writer.as_mut() {
+                            writeln!(
+                                dump_writer,
+                                "ALTER TABLE {schema_table} ALTER COLUMN \"{name}\" TYPE {type_}"
+                            )
+                            .context(IoSnafu {
+                                filename: &options.dump_file,
+                            })?;
+                        }
+                        client
+                            .batch_execute(&format!(
+                                "ALTER TABLE {schema_table} ALTER COLUMN \"{name}\" TYPE {type_}"
+                            ))
+                            .context(PostgresSnafu {})?;
+                    }
+                }
             }
-        };
 
-        let quote = std::str::from_utf8(&[quote_u8])
-            .context(DelimeiterSnafu {})?
-            .to_owned();
+            let (delimiter_u8, quote_u8, _) = csv_dialect(&options, resource);
 
-        let query = format!(
-            "copy {schema_table}({all_columns}) from STDIN WITH (FORMAT CSV, HEADER, QUOTE '{quote}', DELIMITER '{delimiter}', FORCE_NULL ({all_columns}))"
-        );
+            let delimiter = std::str::from_utf8(&[delimiter_u8])
+                .context(DelimeiterSnafu {})?
+                .to_owned();
 
-        if let Some(dump_writer) = dump_writer.as_mut() {
-            let full_path = canonicalize(resource_path).context(IoSnafu {
-                filename: resource_path,
-            })?;
-            let full_path = full_path.to_string_lossy();
-            writeln!(dump_writer, "\\copy {schema_table}({all_columns}) from '{full_path}' WITH (FORMAT CSV, HEADER, QUOTE '{quote}', DELIMITER '{delimiter}', FORCE_NULL ({all_columns}))").context(IoSnafu {filename: &options.dump_file})?;
-        }
+            let quote = std::str::from_utf8(&[quote_u8])
+                .context(DelimeiterSnafu {})?
+                .to_owned();
 
-        let tempdir: Option<TempDir>;
+            let header = if csv_has_header(&options, resource) {
+                "HEADER, "
+            } else {
+                ""
+            };
 
-        let csv_path = if datapackage.ends_with(".zip") {
-            tempdir = Some(TempDir::new().context(IoSnafu {
-                filename: &datapackage,
-            })?);
-            extract_csv_file(
-                &datapackage.to_string(),
-                &resource_path.to_owned(),
-                &tempdir,
-            )?
-        } else {
-            get_path(&datapackage, resource_path, &options)?
-        };
+            let escape = match csv_escape(&options, resource) {
+                Some(escape_u8) => {
+                    let escape = std::str::from_utf8(&[escape_u8])
+                        .context(DelimeiterSnafu {})?
+                        .to_owned();
+                    format!("ESCAPE '{escape}', ")
+                }
+                None => "".into(),
+            };
 
-        if let Some(client) = client.as_mut() {
-            let mut file = std::fs::File::open(&csv_path).context(IoSnafu {
-                filename: csv_path.to_string_lossy().to_owned(),
-            })?;
-            let mut writer = client.copy_in(&query).context(PostgresSnafu {})?;
-            std::io::copy(&mut file, &mut writer).context(IoSnafu {
-                filename: csv_path.to_string_lossy().to_owned(),
-            })?;
-            file.flush().unwrap();
-            writer.finish().context(PostgresSnafu {})?;
+            let primary_key = match client.as_mut() {
+                Some(client) if options.upsert => postgres_primary_key(client, &schema_table)?,
+                _ => resource_primary_key(resource),
+            };
+            let on_conflict = if options.upsert {
+                let clause = upsert_clause(resource, &primary_key, |name| format!("\"{name}\""));
+                if clause.is_none() {
+                    log::warn!("{table} has no primary key so its rows are appended");
+                }
+                clause
+            } else {
+                None
+            };
 
-            if options.delete_input_csv {
-                std::fs::remove_file(&csv_path).context(IoSnafu {
-                    filename: csv_path.to_string_lossy(),
-                })?;
-            }
-        }
-    }
+            // Upserts are copied into a temporary staging table first as `COPY`
+            // can not handle conflicts. A row can only be upserted once per
+            // statement, so only the last staged row for each key is kept.
+            let staging_table = format!("\"_upsert_{table}\"");
+            let copy_table = if on_conflict.is_some() {
+                &staging_table
+            } else {
+                &schema_table
+            };
+            let create_staging = format!(
+                "CREATE TEMP TABLE {staging_table} (LIKE {schema_table} INCLUDING DEFAULTS);"
+            );
+            let upsert_query = on_conflict.as_ref().map(|on_conflict| {
+                format!(
+                    "INSERT INTO {schema_table}({all_columns}) SELECT DISTINCT ON ({primary_key}) {all_columns} FROM {staging_table} ORDER BY {primary_key}, ctid DESC {on_conflict}",
+                    primary_key = primary_key
+                        .iter()
+                        .map(|key| format!("\"{key}\""))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            });
 
-    Ok(())
+            let query = format!(
+                "copy {copy_table}({all_columns}) from STDIN WITH (FORMAT CSV, {header}QUOTE '{quote}', {escape}DELIMITER '{delimiter}', FORCE_NULL ({all_columns}))"
+            );
+
+            let tempdir: Option<TempDir>;
+
+            let csv_path = if datapackage.ends_with(".zip") {
+                tempdir = Some(TempDir::new().context(IoSnafu {
+                    filename: &datapackage,
+                })?);
+                extract_csv_file(
+                    &datapackage.to_string(),
+                    &resource_path.to_owned(),
+                    &tempdir,
+                )?
+            } else {
+                get_path(&datapackage, resource_path, &options)?
+            };
+
+            if let Some(dump_writer) = dump_writer.as_mut() {
+                if on_conflict.is_some() {
+                    writeln!(dump_writer, "{create_staging}").context(IoSnafu {
+                        filename: &options.dump_file,
+                    })?;
+                }
+                let mut cleaner = Cleaner::new(&options, resource);
+                let encoding = copy_encoding(csv_encoding(&options, resource)?);
+                if encoding.is_none() || dump_inline(&options, &table, &cleaner) {
+                    writeln!(dump_writer, "{query};").context(IoSnafu {
+                        filename: &options.dump_file,
+                    })?;
+                    // Every value is quoted so no row can be read as the `\.`
+                    // end marker.
+                    write_clean_csv(
+                        &csv_path,
+                        &mut *dump_writer,
+                        &mut cleaner,
+                        &options,
+                        resource,
+                        csv::QuoteStyle::Always,
+                    )?;
+                    writeln!(dump_writer, "\\.").context(IoSnafu {
+                        filename: &options.dump_file,
+                    })?;
+                    if client.is_none() {
+                        rejects.extend(cleaner.rejects);
+                    }
+                } else {
+                    let full_path = canonicalize(&csv_path).context(IoSnafu {
+                        filename: csv_path.to_string_lossy(),
+                    })?;
+                    let full_path = full_path.to_string_lossy();
+                    let compression = compression::detect(&csv_path).context(IoSnafu {
+                        filename: csv_path.to_string_lossy(),
+                    })?;
+                    let source = match compression {
+                        Some(compression) => {
+                            format!("program '{} {full_path}'", compression.command())
+                        }
+                        None => format!("'{full_path}'"),
+                    };
+                    let encoding = encoding.unwrap_or_default();
+                    writeln!(dump_writer, "\\copy {copy_table}({all_columns}) from {source} WITH (FORMAT CSV, {header}QUOTE '{quote}', {escape}{encoding}DELIMITER '{delimiter}', FORCE_NULL ({all_columns}))").context(IoSnafu {filename: &options.dump_file})?;
+                }
+                if let Some(upsert_query) = &upsert_query {
+                    writeln!(dump_writer, "{upsert_query};\nDROP TABLE {staging_table};").context(
+                        IoSnafu {
+                            filename: &options.dump_file,
+                        },
+                    )?;
+                }
+            }
+
+            if let Some(client) = client.as_mut() {
+                if on_conflict.is_some() {
+                    client
+                        .batch_execute(&create_staging)
+                        .context(PostgresSnafu {})?;
+                }
+                let mut cleaner = Cleaner::new(&options, resource);
+                let mut writer = client.copy_in(&query).context(PostgresSnafu {})?;
+                if !cleaner.needs_rewrite() {
+                    let mut file = open_csv(&csv_path, &options, resource)?;
+                    std::io::copy(&mut file, &mut writer).context(IoSnafu {
+                        filename: csv_path.to_string_lossy().to_owned(),
+                    })?;
+                } else {
+                    // COPY only has one NULL string and only reads plain numbers, so
+                    // values are blanked for FORCE_NULL to pick up and rewritten.
+                    write_clean_csv(
+                        &csv_path,
+                        &mut writer,
+                        &mut cleaner,
+                        &options,
+                        resource,
+                        csv::QuoteStyle::Necessary,
+                    )?;
+                }
+                writer.finish().context(PostgresSnafu {})?;
+                rejects.extend(cleaner.rejects);
+
+                if let Some(upsert_query) = &upsert_query {
+                    // `xmax` is only set on rows that existed before.
+                    let row = client
+                        .query_one(
+                            &format!(
+                                "WITH upserted AS ({upsert_query} RETURNING (xmax = 0) AS inserted) \
+                                 SELECT count(*) FILTER (WHERE inserted), count(*) FILTER (WHERE NOT inserted), \
+                                 (SELECT count(*) FROM {staging_table}) FROM upserted"
+                            ),
+                            &[],
+                        )
+                        .context(PostgresSnafu {})?;
+                    client
+                        .batch_execute(&format!("DROP TABLE {staging_table};"))
+                        .context(PostgresSnafu {})?;
+                    let inserted: i64 = row.get(0);
+                    let updated: i64 = row.get(1);
+                    let staged: i64 = row.get(2);
+                    upserts.push(Upsert {
+                        table: table.clone(),
+                        inserted: inserted as u64,
+                        updated: updated as u64,
+                        skipped: (staged - inserted - updated) as u64,
+                    });
+                }
+
+                if options.delete_input_csv {
+                    std::fs::remove_file(&csv_path).context(IoSnafu {
+                        filename: csv_path.to_string_lossy(),
+                    })?;
+                }
+            }
+        }
+
+        if options.swap_schema {
+            let swap = format!(
+                "DROP SCHEMA IF EXISTS \"{live_schema}_old\" CASCADE;\n\
+                 DO $$BEGIN IF EXISTS (SELECT FROM pg_namespace WHERE nspname = '{live_name}') THEN ALTER SCHEMA \"{live_schema}\" RENAME TO \"{live_schema}_old\"; END IF; END$$;\n\
+                 ALTER SCHEMA \"{load_schema}\" RENAME TO \"{live_schema}\";\n\
+                 DROP SCHEMA IF EXISTS \"{live_schema}_old\" CASCADE;\n",
+                live_name = live_schema.replace('\'', "''"),
+                load_schema = options.schema,
+            );
+            if let Some(dump_writer) = dump_writer.as_mut() {
+                write!(dump_writer, "{swap}").context(IoSnafu {
+                    filename: &options.dump_file,
+                })?;
+            }
+            if let Some(client) = client.as_mut() {
+                client.batch_execute(&swap).context(PostgresSnafu {})?;
+            }
+        }
+        Ok(())
+    };
+
+    if let Err(error) = load() {
+        if transaction && let Some(client) = client.as_mut() {
+            // The load error is more useful than any error from the rollback.
+            let _ = client.batch_execute("ROLLBACK;");
+        }
+        return Err(error);
+    }
+
+    if transaction {
+        if let Some(dump_writer) = dump_writer.as_mut() {
+            writeln!(dump_writer, "COMMIT;").context(IoSnafu {
+                filename: &options.dump_file,
+            })?;
+        }
+        if let Some(client) = client.as_mut() {
+            client.batch_execute("COMMIT;").context(PostgresSnafu {})?;
+        }
+    }
+
+    write_rejects(&options, &rejects)?;
+    write_upserts(&options, &upserts)?;
+
+    Ok(())
+}
+
+/// TLS settings for postgres connections, as named in libpq connection
+/// strings.
+#[derive(Debug, Default, PartialEq)]
+struct SslSettings {
+    mode: String,
+    root_cert: String,
+    cert: String,
+    key: String,
+}
+
+impl SslSettings {
+    fn set(&mut self, key: &str, value: String) -> bool {
+        match key {
+            "sslmode" => self.mode = value,
+            "sslrootcert" => self.root_cert = value,
+            "sslcert" => self.cert = value,
+            "sslkey" => self.key = value,
+            _ => return false,
+        }
+        true
+    }
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// `key=value` pairs of a connection string like `host=localhost
+/// password='a secret'`.
+fn connection_pairs(conf: &str) -> Vec<(String, String)> {
+    let mut pairs = vec![];
+    let mut chars = conf.chars().peekable();
+    loop {
+        while chars.next_if(|char| char.is_whitespace()).is_some() {}
+        let mut key = String::new();
+        while let Some(char) = chars.next_if(|char| *char != '=' && !char.is_whitespace()) {
+            key.push(char);
+        }
+        if key.is_empty() {
+            break;
+        }
+        while chars
+            .next_if(|char| char.is_whitespace() || *char == '=')
+            .is_some()
+        {}
+        let mut value = String::new();
+        let quoted = chars.next_if_eq(&'\'').is_some();
+        while let Some(char) = chars.next() {
+            match char {
+                '\\' => value.extend(chars.next()),
+                '\'' if quoted => break,
+                char if char.is_whitespace() && !quoted => break,
+                char => value.push(char),
+            }
+        }
+        pairs.push((key, value));
+    }
+    pairs
+}
+
+/// Takes the TLS settings out of a connection string, as the postgres crate
+/// does not know the certificate settings or the `verify-ca` and
+/// `verify-full` modes.
+fn split_ssl_settings(conf: &str) -> (String, SslSettings) {
+    let mut settings = SslSettings::default();
+    let conf = conf.trim();
+
+    if conf.starts_with("postgres://") || conf.starts_with("postgresql://") {
+        let Some((base, query)) = conf.split_once('?') else {
+            return (conf.to_owned(), settings);
+        };
+        let params: Vec<&str> = query
+            .split('&')
+            .filter(|param| {
+                let (key, value) = param.split_once('=').unwrap_or((param, ""));
+                !settings.set(key, percent_decode(value))
+            })
+            .collect();
+        if params.is_empty() {
+            return (base.to_owned(), settings);
+        }
+        return (format!("{base}?{}", params.join("&")), settings);
+    }
+
+    let pairs: Vec<String> = connection_pairs(conf)
+        .into_iter()
+        .filter(|(key, value)| !settings.set(key, value.clone()))
+        .map(|(key, value)| {
+            let value = value.replace('\\', "\\\\").replace('\'', "\\'");
+            format!("{key}='{value}'")
+        })
+        .collect();
+    (pairs.join(" "), settings)
+}
+
+/// Connects to postgres using `sslmode`, `sslrootcert`, `sslcert` and `sslkey`
+/// from the connection string, or the options which take priority. Modes
+/// follow libpq: `prefer` and `require` do not check the server certificate
+/// unless a root certificate is given, `verify-ca` checks it was signed by a
+/// trusted root and `verify-full` also checks the host name. Client keys need
+/// to be PKCS#8 PEM files.
+fn connect_postgres(conf: &str, options: &Options) -> Result<Client, Error> {
+    let (conf, mut settings) = split_ssl_settings(conf);
+    for (key, value) in [
+        ("sslmode", &options.ssl_mode),
+        ("sslrootcert", &options.ssl_root_cert),
+        ("sslcert", &options.ssl_cert),
+        ("sslkey", &options.ssl_key),
+    ] {
+        if !value.is_empty() {
+            settings.set(key, value.clone());
+        }
+    }
+    let mut config: postgres::Config = conf.parse().context(PostgresSnafu {})?;
+
+    #[cfg(not(feature = "tls"))]
+    {
+        match settings.mode.as_str() {
+            "" | "disable" | "allow" | "prefer" => {
+                config.ssl_mode(postgres::config::SslMode::Disable);
+                config.connect(NoTls).context(PostgresSnafu {})
+            }
+            "require" | "verify-ca" | "verify-full" => DatapackageConvertSnafu {
+                message: format!(
+                    "sslmode={} needs csvs_convert built with the `tls` feature",
+                    settings.mode
+                ),
+            }
+            .fail(),
+            mode => DatapackageConvertSnafu {
+                message: format!("Unknown sslmode {mode}"),
+            }
+            .fail(),
+        }
+    }
+
+    #[cfg(feature = "tls")]
+    {
+        use postgres::config::SslMode;
+
+        let has_root_cert = !settings.root_cert.is_empty() && settings.root_cert != "system";
+        // `None` skips checking the server certificate, otherwise whether
+        // the host name is checked too.
+        let check_host_name = match settings.mode.as_str() {
+            "disable" => {
+                config.ssl_mode(SslMode::Disable);
+                return config.connect(NoTls).context(PostgresSnafu {});
+            }
+            "" | "allow" | "prefer" => {
+                config.ssl_mode(SslMode::Prefer);
+                has_root_cert.then_some(false)
+            }
+            "require" => {
+                config.ssl_mode(SslMode::Require);
+                has_root_cert.then_some(false)
+            }
+            "verify-ca" => {
+                config.ssl_mode(SslMode::Require);
+                Some(false)
+            }
+            "verify-full" => {
+                config.ssl_mode(SslMode::Require);
+                Some(true)
+            }
+            mode => {
+                return DatapackageConvertSnafu {
+                    message: format!("Unknown sslmode {mode}"),
+                }
+                .fail();
+            }
+        };
+
+        let mut builder = native_tls::TlsConnector::builder();
+        match check_host_name {
+            None => builder.danger_accept_invalid_certs(true),
+            Some(check_host_name) => builder.danger_accept_invalid_hostnames(!check_host_name),
+        };
+
+        if has_root_cert {
+            let pem = std::fs::read(&settings.root_cert).context(IoSnafu {
+                filename: &settings.root_cert,
+            })?;
+            builder.disable_built_in_roots(true);
+            for certificate in native_tls::Certificate::stack_from_pem(&pem).context(TlsSnafu {})? {
+                builder.add_root_certificate(certificate);
+            }
+        }
+
+        if !settings.cert.is_empty() {
+            let cert = std::fs::read(&settings.cert).context(IoSnafu {
+                filename: &settings.cert,
+            })?;
+            let key = std::fs::read(&settings.key).context(IoSnafu {
+                filename: &settings.key,
+            })?;
+            builder.identity(native_tls::Identity::from_pkcs8(&cert, &key).context(TlsSnafu {})?);
+        }
+
+        let connector = builder.build().context(TlsSnafu {})?;
+        config
+            .connect(postgres_native_tls::MakeTlsConnector::new(connector))
+            .context(PostgresSnafu {})
+    }
 }
 
+/// Column names with their database types.
+type Columns = Vec<(String, String)>;
+
 fn get_column_changes(
     resource: &Value,
     existing_columns: HashMap<String, String>,
-) -> (Vec<(String, String)>, Vec<String>) {
+    db_type: fn(&Value) -> String,
+) -> (Columns, Columns) {
     let mut add_columns = vec![];
     let mut alter_columns = vec![];
     if let Some(fields) = resource["schema"]["fields"].as_array() {
         for field in fields {
             if let Some(name) = field["name"].as_str() {
-                if let Some(type_) = field["type"].as_str() {
+                if field["type"].is_string() {
                     let existing_column_type = existing_columns.get(name);
                     if let Some(existing_column_type) = existing_column_type {
-                        if to_db_type(
-                            type_.to_string(),
-                            field["format"].as_str().unwrap_or("").into(),
-                        )
-                        .to_lowercase()
-                            != existing_column_type.to_lowercase()
-                        {
-                            alter_columns.push(name.to_owned());
+                        if let Some(type_) = column_change(existing_column_type, &db_type(field)) {
+                            alter_columns.push((name.to_owned(), type_));
                         }
                     } else {
-                        add_columns.push((
-                            name.to_owned(),
-                            to_db_type(
-                                type_.to_string(),
-                                field["format"].as_str().unwrap_or("").into(),
-                            ),
-                        ))
+                        add_columns.push((name.to_owned(), db_type(field)))
                     }
                 }
             }
@@ -2175,7 +4218,7 @@ fn create_ods_sheet(
     resource: Value,
     workbook: &mut spreadsheet_ods::WorkBook,
     options: &Options,
-) -> Result<(), Error> {
+) -> Result<Vec<Reject>, Error> {
     let mut bold_format = spreadsheet_ods::CellStyle::new_empty();
     bold_format.set_font_bold();
     let bold_format_ref = workbook.add_cellstyle(bold_format);
@@ -2184,11 +4227,13 @@ fn create_ods_sheet(
     let base_format_ref = workbook.add_cellstyle(base_format);
 
     let mut field_types = vec![];
+    let mut field_names = vec![];
     if let Some(fields_vec) = resource["schema"]["fields"].as_array() {
         for value in fields_vec {
             if let Some(field_type) = value["type"].as_str() {
                 field_types.push(field_type.to_owned());
             }
+            field_names.push(value["name"].as_str().unwrap_or_default().to_owned());
         }
     };
 
@@ -2199,6 +4244,20 @@ fn create_ods_sheet(
         }
     );
 
+    let mut cleaner = Cleaner::new(options, &resource);
+
+    // Columns with only a few allowed values get a drop-down list.
+    let validations: Vec<Option<ValidationRef>> = enum_values(&resource)
+        .into_iter()
+        .map(|values| {
+            values.map(|values| {
+                let mut validation = Validation::new();
+                validation.set_condition(Condition::content_is_in_list(&values));
+                workbook.add_validation(validation)
+            })
+        })
+        .collect();
+
     let mut title = String::new();
 
     if let Some(t) = resource["name"].as_str() {
@@ -2222,7 +4281,23 @@ fn create_ods_sheet(
 
     let mut worksheet = spreadsheet_ods::Sheet::new(new_title);
 
+    // CSVs without a header row get one written from the field names.
+    let row_offset = if csv_has_header(options, &resource) {
+        0
+    } else {
+        for (col_index, name) in field_names.iter().enumerate() {
+            worksheet.set_styled_value(
+                0,
+                col_index.try_into().expect("already checked field count"),
+                name,
+                &bold_format_ref,
+            );
+        }
+        1
+    };
+
     for (row_num, row) in csv_reader.into_records().enumerate() {
+        let row_num = row_num + row_offset;
         let this_row = row.context(CSVSnafu { filename: &title })?;
 
         let mut format = base_format_ref.clone();
@@ -2245,6 +4320,26 @@ fn create_ods_sheet(
         }
 
         for (col_index, value) in this_row.iter().enumerate() {
+            if row_num > 0
+                && let Some(Some(validation)) = validations.get(col_index)
+            {
+                worksheet.set_validation(
+                    row_num.try_into().expect("already tested length of string"),
+                    col_index.try_into().expect("already checked field count"),
+                    validation,
+                );
+            }
+
+            // Missing and rejected values are left as empty cells.
+            let value = if row_num == 0 {
+                Cow::Borrowed(value)
+            } else {
+                match cleaner.clean(row_num, col_index, value) {
+                    Some(value) => value,
+                    None => continue,
+                }
+            };
+
             let mut cell = value.to_string();
 
             if ["number", "integer"].contains(&field_types[col_index].as_str()) {
@@ -2289,7 +4384,7 @@ fn create_ods_sheet(
     }
 
     workbook.push_sheet(worksheet);
-    Ok(())
+    Ok(cleaner.rejects)
 }
 
 pub fn csvs_to_ods(ods_path: String, csvs: Vec<PathBuf>) -> Result<Value, Error> {
@@ -2318,6 +4413,25 @@ pub fn csvs_to_ods_with_options(
         .delimiter(options.delimiter)
         .quote(options.quote)
         .all_strings(options.all_strings)
+        .has_header(options.has_header)
+        .field_names(options.field_names.clone())
+        .encoding(options.encoding.clone())
+        .missing_values(options.missing_values.clone())
+        .decimal_char(options.decimal_char)
+        .group_char(options.group_char)
+        .bare_number(options.bare_number)
+        .date_order(options.date_order)
+        .datetime_formats(options.datetime_formats.clone())
+        .date_formats(options.date_formats.clone())
+        .time_formats(options.time_formats.clone())
+        .max_invalid_ratio(options.max_invalid_ratio)
+        .detect_keys(options.detect_keys)
+        .detect_foreign_keys(options.detect_foreign_keys)
+        .detect_enums(options.detect_enums)
+        .constraints(options.constraints)
+        .unique_constraints(options.unique_constraints)
+        .detect_formats(options.detect_formats)
+        .detectors(options.detectors.clone())
         .build();
     let datapackage = describe::describe_files(csvs, PathBuf::new(), &describe_options)
         .context(DescribeSnafu {})?;
@@ -2359,6 +4473,8 @@ pub fn datapackage_to_ods_with_options(
 
     let mut workbook = spreadsheet_ods::WorkBook::new_empty();
 
+    let mut rejects = vec![];
+
     for resource in resources_option.unwrap() {
         let resource_path = resource["path"].as_str().unwrap();
 
@@ -2379,20 +4495,23 @@ pub fn datapackage_to_ods_with_options(
 
         let csv_reader = get_csv_reader_builder(&options, resource)
             .has_headers(false)
-            .from_path(&csv_path)
-            .context(CSVSnafu {
-                filename: csv_path.to_string_lossy().to_string(),
-            })?;
+            .from_reader(open_csv(&csv_path, &options, resource)?);
 
         if options.delete_input_csv {
             std::fs::remove_file(&csv_path).context(IoSnafu {
                 filename: csv_path.to_string_lossy(),
             })?;
         }
-        create_ods_sheet(csv_reader, resource.clone(), &mut workbook, &options)?;
+        rejects.extend(create_ods_sheet(
+            csv_reader,
+            resource.clone(),
+            &mut workbook,
+            &options,
+        )?);
     }
 
     spreadsheet_ods::write_ods(&mut workbook, &ods_path).context(OdsSnafu {})?;
+    write_rejects(&options, &rejects)?;
 
     Ok(())
 }
@@ -2648,86 +4767,1135 @@ mod tests {
     }
 
     #[test]
-    fn test_csvs_all_types_to_sqlite() {
+    fn test_csvs_no_header_to_sqlite() {
         let tmp_dir = TempDir::new().unwrap();
         let tmp = tmp_dir.path().to_owned();
 
-        csvs_to_sqlite_with_options(
+        let datapackage = csvs_to_sqlite_with_options(
             tmp.join("sqlite.db").to_string_lossy().into(),
-            vec![
-                "src/fixtures/all_types.csv".into(),
-                "src/fixtures/all_types_semi_colon.csv".into(),
-            ],
-            Options::builder().stats(true).build(),
+            vec!["fixtures/no_header.csv".into()],
+            Options::builder().has_header(false).build(),
         )
         .unwrap();
+        assert_eq!(datapackage["resources"][0]["dialect"]["header"], false);
 
-        assert!(tmp.join("sqlite.db").exists());
+        let conn = Connection::open(tmp.join("sqlite.db")).unwrap();
+        let mut stmt = conn
+            .prepare("select field_1, field_2 from no_header")
+            .unwrap();
+        let mut rows = stmt.query([]).unwrap();
+
+        let mut output: Vec<(i64, String)> = vec![];
+        while let Some(row) = rows.next().unwrap() {
+            output.push((row.get(0).unwrap(), row.get(1).unwrap()));
+        }
+        assert_eq!(
+            output,
+            vec![(1, "Ada".into()), (2, "Bert".into()), (3, "Cleo".into())]
+        );
     }
 
     #[test]
-    fn test_csvs_to_sqlite_large() {
+    fn test_csvs_windows_1252_to_sqlite() {
         let tmp_dir = TempDir::new().unwrap();
         let tmp = tmp_dir.path().to_owned();
 
-        let options = Options::builder()
-            .stats(true)
-            .threads(8)
-            .delimiter(Some(b','));
-
-        csvs_to_sqlite_with_options(
+        let datapackage = csvs_to_sqlite_with_options(
             tmp.join("sqlite.db").to_string_lossy().into(),
-            vec![
-                "fixtures/large/csv/data.csv".into(),
-                "fixtures/large/csv/daily_16.csv".into(),
-                "fixtures/large/csv/data_weather.csv".into(),
-            ],
-            options.build(),
+            vec!["fixtures/windows_1252.csv".into()],
+            Options::builder().threads(2).build(),
         )
         .unwrap();
+        assert_eq!(datapackage["resources"][0]["encoding"], "windows-1252");
 
-        assert!(tmp.join("sqlite.db").exists());
+        let conn = Connection::open(tmp.join("sqlite.db")).unwrap();
+        let mut stmt = conn
+            .prepare("select name, town, population from windows_1252")
+            .unwrap();
+        let mut rows = stmt.query([]).unwrap();
+
+        let mut output: Vec<(String, String, i64)> = vec![];
+        while let Some(row) = rows.next().unwrap() {
+            output.push((
+                row.get(0).unwrap(),
+                row.get(1).unwrap(),
+                row.get(2).unwrap(),
+            ));
+        }
+        assert_eq!(
+            output,
+            vec![
+                ("José".into(), "Málaga".into(), 578460),
+                ("François".into(), "Orléans".into(), 116238),
+                ("Zoë".into(), "Köln".into(), 1084831)
+            ]
+        );
     }
 
     #[test]
-    fn test_ods_from_csvs() {
+    fn test_csvs_compressed_to_sqlite() {
         let tmp_dir = TempDir::new().unwrap();
         let tmp = tmp_dir.path().to_owned();
-        //let tmp = PathBuf::from("/tmp");
 
-        csvs_to_ods(
-            tmp.join("output.ods").to_string_lossy().into(),
+        let datapackage = csvs_to_sqlite_with_options(
+            tmp.join("sqlite.db").to_string_lossy().into(),
             vec![
-                "src/fixtures/all_types.csv".into(),
-                "src/fixtures/all_types_semi_colon.csv".into(),
+                "fixtures/compressed/games_gz.csv.gz".into(),
+                "fixtures/compressed/games_zst.csv.zst".into(),
+                "fixtures/compressed/games_bz2.csv.bz2".into(),
             ],
+            Options::builder().threads(2).build(),
         )
         .unwrap();
+        assert_eq!(datapackage["resources"][0]["name"], "games_gz");
+
+        let conn = Connection::open(tmp.join("sqlite.db")).unwrap();
+        for table in ["games_gz", "games_zst", "games_bz2"] {
+            let mut stmt = conn
+                .prepare(&format!("select id, title from {table}"))
+                .unwrap();
+            let mut rows = stmt.query([]).unwrap();
+
+            let mut output: Vec<(i64, String)> = vec![];
+            while let Some(row) = rows.next().unwrap() {
+                output.push((row.get(0).unwrap(), row.get(1).unwrap()));
+            }
+            assert_eq!(
+                output,
+                vec![
+                    (1, "game1_add_resource".into()),
+                    (2, "game2_add_resource".into())
+                ]
+            );
+        }
     }
 
     #[test]
-    fn test_xlsx_from_csvs() {
+    fn test_csvs_missing_values_to_sqlite() {
         let tmp_dir = TempDir::new().unwrap();
         let tmp = tmp_dir.path().to_owned();
-        // let tmp = PathBuf::from("/tmp");
 
-        csvs_to_xlsx(
-            tmp.join("output.xlsx").to_string_lossy().into(),
-            vec![
-                "src/fixtures/all_types.csv".into(),
-                "src/fixtures/all_types_semi_colon.csv".into(),
-            ],
+        let datapackage = csvs_to_sqlite_with_options(
+            tmp.join("sqlite.db").to_string_lossy().into(),
+            vec!["fixtures/missing_values.csv".into()],
+            Options::builder()
+                .missing_values(vec!["NA".into(), "-".into(), "NULL".into()])
+                .build(),
         )
         .unwrap();
-    }
-
-    #[test]
-    fn test_large_ods_from_csvs() {
-        let tmp_dir = TempDir::new().unwrap();
-        let tmp = tmp_dir.path().to_owned();
-        // let tmp = PathBuf::from("/tmp");
+        insta::assert_yaml_snapshot!(datapackage["resources"][0]["schema"]);
 
-        csvs_to_ods(
+        let conn = Connection::open(tmp.join("sqlite.db")).unwrap();
+        let mut stmt = conn
+            .prepare("select id, score, note from missing_values")
+            .unwrap();
+        let mut rows = stmt.query([]).unwrap();
+
+        let mut output: Vec<(Option<i64>, Option<f64>, Option<String>)> = vec![];
+        while let Some(row) = rows.next().unwrap() {
+            output.push((
+                row.get(0).unwrap(),
+                row.get(1).unwrap(),
+                row.get(2).unwrap(),
+            ));
+        }
+        assert_eq!(
+            output,
+            vec![
+                (Some(1), Some(1.5), Some("a".into())),
+                (Some(2), None, Some("b".into())),
+                (None, None, None),
+                (Some(4), Some(2.5), None)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sized_db_types() {
+        let field = json!({"name": "a", "type": "number", "precision": 7, "scale": 2});
+        assert_eq!(field_db_type(&field), "NUMERIC(7,2)");
+        let field = json!({"name": "a", "type": "integer", "bitWidth": 32});
+        assert_eq!(field_db_type(&field), "INTEGER");
+        let field = json!({"name": "a", "type": "integer", "precision": 24});
+        assert_eq!(field_db_type(&field), "NUMERIC(24,0)");
+        let field = json!({"name": "a", "type": "integer"});
+        assert_eq!(field_db_type(&field), "BIGINT");
+        let field = json!({"name": "a", "type": "string", "constraints": {"maxLength": 4}});
+        assert_eq!(field_db_type(&field), "VARCHAR(4)");
+        let field = json!({"name": "a", "type": "number", "precision": 20, "scale": 7});
+        assert_eq!(field_db_type(&field), "NUMERIC(20,7)");
+        assert_eq!(sqlite_field_db_type(&field), "TEXT");
+        assert_eq!(strict_field_db_type(&field), "TEXT");
+        let field = json!({"name": "a", "type": "number", "precision": 7, "scale": 2});
+        assert_eq!(sqlite_field_db_type(&field), "NUMERIC(7,2)");
+        for (type_, db_type) in [
+            ("date", "DATE"),
+            ("time", "TIME"),
+            ("datetime", "TIMESTAMP"),
+            ("datetime_tz", "TIMESTAMPTZ"),
+        ] {
+            assert_eq!(field_db_type(&json!({"name": "a", "type": type_})), db_type);
+        }
+        assert_eq!(column_change("text", "VARCHAR(4)"), None);
+
+        assert_eq!(column_change("int8", "BIGINT"), None);
+        assert_eq!(column_change("int8", "SMALLINT"), None);
+        assert_eq!(column_change("int2", "INTEGER"), Some("INTEGER".into()));
+        assert_eq!(
+            column_change("numeric", "NUMERIC(7,2)"),
+            Some("NUMERIC".into())
+        );
+        assert_eq!(column_change("int8", "NUMERIC(7,2)"), Some("TEXT".into()));
+
+        assert_eq!(column_change("timestamp", "DATE"), None);
+        assert_eq!(column_change("TIMESTAMP", "DATE"), None);
+        assert_eq!(column_change("date", "TIMESTAMP"), Some("TIMESTAMP".into()));
+        assert_eq!(column_change("timestamptz", "TIMESTAMP"), None);
+        assert_eq!(column_change("date", "TIME"), Some("TEXT".into()));
+    }
+
+    #[test]
+    fn test_postgres_format_types() {
+        let field = json!({"name": "a", "type": "string", "format": "uuid", "constraints": {"minLength": 36, "maxLength": 36}});
+        assert_eq!(postgres_field_type(&field), "UUID");
+        assert_eq!(field_db_type(&field), "VARCHAR(36)");
+        let field = json!({"name": "a", "type": "string", "format": "ipv6"});
+        assert_eq!(postgres_field_type(&field), "INET");
+        let field = json!({"name": "a", "type": "string", "format": "latlong"});
+        assert_eq!(postgres_field_type(&field), "POINT");
+        let field = json!({"name": "a", "type": "string", "format": "email"});
+        assert_eq!(postgres_field_type(&field), "TEXT");
+
+        let table = render_postgres_table(json!({
+            "name": "formats",
+            "schema": {"fields": [
+                {"name": "id", "type": "string", "format": "uuid", "constraints": {"required": true, "minLength": 36, "maxLength": 36}},
+                {"name": "code", "type": "string", "format": "string", "constraints": {"minLength": 2, "maxLength": 2}},
+            ]}
+        }))
+        .unwrap();
+        assert!(table.contains(r#""id" UUID NOT NULL"#));
+        assert!(!table.contains(r#"length("id")"#));
+        assert!(table.contains(r#""code" VARCHAR(2) CHECK (length("code") >= 2)"#));
+    }
+
+    #[test]
+    fn test_formats_to_xlsx() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+
+        csvs_to_xlsx_with_options(
+            tmp.join("output.xlsx").to_string_lossy().into(),
+            vec!["fixtures/formats.csv".into()],
+            Options::builder().detect_formats(true).build(),
+        )
+        .unwrap();
+        let mut archive =
+            zip::ZipArchive::new(File::open(tmp.join("output.xlsx")).unwrap()).unwrap();
+        let mut sheet = String::new();
+        std::io::Read::read_to_string(
+            &mut archive.by_name("xl/worksheets/sheet1.xml").unwrap(),
+            &mut sheet,
+        )
+        .unwrap();
+        assert!(sheet.contains(r#"<hyperlink ref="D2""#));
+        assert!(sheet.contains(r#"<hyperlink ref="D4""#));
+        assert!(!sheet.contains(r#"<hyperlink ref="C2""#));
+    }
+
+    #[test]
+    fn test_csvs_keys_to_sqlite() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+
+        csvs_to_sqlite_with_options(
+            tmp.join("sqlite.db").to_string_lossy().into(),
+            vec!["fixtures/keys.csv".into(), "fixtures/pair_keys.csv".into()],
+            Options::builder().detect_keys(true).build(),
+        )
+        .unwrap();
+
+        let conn = Connection::open(tmp.join("sqlite.db")).unwrap();
+        let mut stmt = conn
+            .prepare("select name from pragma_table_info('keys') where pk > 0")
+            .unwrap();
+        let key: String = stmt.query_row([], |row| row.get(0)).unwrap();
+        assert_eq!(key, "id");
+
+        let mut stmt = conn
+            .prepare("select name from pragma_table_info('pair_keys') where pk > 0 order by pk")
+            .unwrap();
+        let keys: Vec<String> = stmt
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .map(|name| name.unwrap())
+            .collect();
+        assert_eq!(keys, vec!["country", "year"]);
+    }
+
+    #[test]
+    fn test_csvs_foreign_keys_to_sqlite() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+
+        csvs_to_sqlite_with_options(
+            tmp.join("sqlite.db").to_string_lossy().into(),
+            vec![
+                "fixtures/foreign_keys/cities.csv".into(),
+                "fixtures/foreign_keys/countries.csv".into(),
+            ],
+            Options::builder().detect_foreign_keys(true).build(),
+        )
+        .unwrap();
+
+        let conn = Connection::open(tmp.join("sqlite.db")).unwrap();
+        let reference: (String, String, String) = conn
+            .query_row(
+                "select \"table\", \"from\", \"to\" from pragma_foreign_key_list('cities')",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            reference,
+            ("countries".into(), "country_id".into(), "id".into())
+        );
+    }
+
+    #[test]
+    fn test_change_sqlite_column_type() {
+        let sql = "CREATE TABLE [cities] (\n[id] SMALLINT CHECK ([id] >= 1)\n , [population] INTEGER CHECK ([population] >= 100) CHECK ([population] <= 900)\n)";
+        assert_eq!(
+            change_sqlite_column_type(sql, "population", "INTEGER", "TEXT").unwrap(),
+            "CREATE TABLE [cities] (\n[id] SMALLINT CHECK ([id] >= 1)\n , [population] TEXT\n)"
+        );
+        assert_eq!(
+            change_sqlite_column_type(sql, "id", "SMALLINT", "INTEGER").unwrap(),
+            "CREATE TABLE [cities] (\n[id] INTEGER\n , [population] INTEGER CHECK ([population] >= 100) CHECK ([population] <= 900)\n)"
+        );
+        assert!(change_sqlite_column_type(sql, "name", "TEXT", "INTEGER").is_none());
+    }
+
+    #[test]
+    fn test_evolve_sqlite_type_change() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+        let db_path: String = tmp.join("sqlite.db").to_string_lossy().into();
+
+        csvs_to_sqlite_with_options(
+            db_path.clone(),
+            vec![
+                "fixtures/foreign_keys/cities.csv".into(),
+                "fixtures/foreign_keys/countries.csv".into(),
+            ],
+            Options::builder().detect_foreign_keys(true).build(),
+        )
+        .unwrap();
+
+        std::fs::write(
+            tmp.join("cities.csv"),
+            "id,name,country_id,population\n6,Porto,2,unknown\n",
+        )
+        .unwrap();
+
+        csvs_to_sqlite_with_options(
+            db_path.clone(),
+            vec![tmp.join("cities.csv")],
+            Options::builder().evolve(true).build(),
+        )
+        .unwrap();
+
+        let conn = Connection::open(&db_path).unwrap();
+        let population_type: String = conn
+            .query_row(
+                "select type from pragma_table_info('cities') where name = 'population'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(population_type, "TEXT");
+
+        let mut stmt = conn
+            .prepare("select typeof(population), count(*) from cities group by 1")
+            .unwrap();
+        let types: Vec<(String, i64)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        assert_eq!(types, vec![("text".into(), 6)]);
+
+        let reference: (String, String, String) = conn
+            .query_row(
+                "select \"table\", \"from\", \"to\" from pragma_foreign_key_list('cities')",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            reference,
+            ("countries".into(), "country_id".into(), "id".into())
+        );
+
+        let index: String = conn
+            .query_row(
+                "select name from pragma_index_list('cities') where origin = 'c'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(index, "idx_cities_country_id");
+
+        // Rebuilding a referenced table keeps a journal and leaves the
+        // referencing rows alone.
+        std::fs::write(tmp.join("countries.csv"), "id,name\nPT,Portugal\n").unwrap();
+        let dump_file: String = tmp.join("dump.sql").to_string_lossy().into();
+        csvs_to_sqlite_with_options(
+            db_path.clone(),
+            vec![tmp.join("countries.csv")],
+            Options::builder()
+                .evolve(true)
+                .dump_file(dump_file.clone())
+                .build(),
+        )
+        .unwrap();
+
+        let dump = std::fs::read_to_string(&dump_file).unwrap();
+        let rebuild_start = dump.find("PRAGMA journal_mode = MEMORY;").unwrap();
+        let foreign_keys_off = dump.find("PRAGMA foreign_keys = OFF;").unwrap();
+        let drop = dump.find("DROP TABLE [countries];").unwrap();
+        let rebuild_end = dump.rfind("PRAGMA journal_mode = OFF;").unwrap();
+        assert!(rebuild_start < foreign_keys_off && foreign_keys_off < drop && drop < rebuild_end);
+
+        let counts: (i64, i64) = conn
+            .query_row(
+                "select (select count(*) from cities), (select count(*) from countries)",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(counts, (6, 4));
+    }
+
+    #[test]
+    fn test_upsert_clause() {
+        let resource = json!({
+            "schema": {
+                "fields": [{"name": "id"}, {"name": "year"}, {"name": "total"}],
+                "primaryKey": ["id", "year"]
+            }
+        });
+        let primary_key = resource_primary_key(&resource);
+        assert_eq!(
+            upsert_clause(&resource, &primary_key, |name| format!("[{name}]")).unwrap(),
+            "ON CONFLICT ([id], [year]) DO UPDATE SET [total] = excluded.[total]"
+        );
+        assert_eq!(
+            upsert_clause(&resource, &["id".into()], |name| format!("[{name}]")).unwrap(),
+            "ON CONFLICT ([id]) DO UPDATE SET [year] = excluded.[year], [total] = excluded.[total]"
+        );
+
+        let resource = json!({"schema": {"fields": [{"name": "id"}], "primaryKey": "id"}});
+        let primary_key = resource_primary_key(&resource);
+        assert_eq!(
+            upsert_clause(&resource, &primary_key, |name| format!("\"{name}\"")).unwrap(),
+            "ON CONFLICT (\"id\") DO NOTHING"
+        );
+
+        let resource = json!({"schema": {"fields": [{"name": "id"}]}});
+        let primary_key = resource_primary_key(&resource);
+        assert!(upsert_clause(&resource, &primary_key, |name| format!("[{name}]")).is_none());
+    }
+
+    #[test]
+    fn test_csvs_upsert_to_sqlite() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+        let db_path: String = tmp.join("sqlite.db").to_string_lossy().into();
+
+        csvs_to_sqlite_with_options(
+            db_path.clone(),
+            vec!["fixtures/typed_values.csv".into()],
+            Options::builder().detect_keys(true).build(),
+        )
+        .unwrap();
+
+        std::fs::write(
+            tmp.join("typed_values.csv"),
+            "id,amount,active,name,joined\n2,7.5,true,b,2024-02-03\n3,1.25,false,c,2024-03-04\n",
+        )
+        .unwrap();
+
+        let upsert_csv = tmp.join("upserts.csv");
+        csvs_to_sqlite_with_options(
+            db_path.clone(),
+            vec![tmp.join("typed_values.csv")],
+            Options::builder()
+                .detect_keys(true)
+                .upsert(true)
+                .upsert_csv(upsert_csv.to_string_lossy().into())
+                .build(),
+        )
+        .unwrap();
+
+        let conn = Connection::open(&db_path).unwrap();
+        let mut stmt = conn
+            .prepare("select id, amount, name from typed_values order by id")
+            .unwrap();
+        let rows: Vec<(i64, f64, Option<String>)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                (1, 1.5, Some("a".into())),
+                (2, 7.5, Some("b".into())),
+                (3, 1.25, Some("c".into()))
+            ]
+        );
+
+        assert_eq!(
+            std::fs::read_to_string(upsert_csv).unwrap(),
+            "table,inserted,updated,skipped\ntyped_values,1,1,0\n"
+        );
+    }
+
+    #[test]
+    fn test_upsert_existing_key_sqlite() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+        let db_path: String = tmp.join("sqlite.db").to_string_lossy().into();
+
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE codes (code TEXT PRIMARY KEY);
+             CREATE TABLE prices (id INTEGER PRIMARY KEY, price REAL);
+             INSERT INTO codes VALUES ('a'), ('b');
+             INSERT INTO prices VALUES (1, 1.5);",
+        )
+        .unwrap();
+
+        std::fs::write(tmp.join("codes.csv"), "code\nb\nc\n").unwrap();
+        std::fs::write(tmp.join("prices.csv"), "id,price\n1,2.5\n2,3.5\n").unwrap();
+
+        // The resources have no primary key so the tables' keys are used.
+        let upsert_csv = tmp.join("upserts.csv");
+        csvs_to_sqlite_with_options(
+            db_path.clone(),
+            vec![tmp.join("codes.csv"), tmp.join("prices.csv")],
+            Options::builder()
+                .upsert(true)
+                .upsert_csv(upsert_csv.to_string_lossy().into())
+                .build(),
+        )
+        .unwrap();
+
+        let prices: Vec<(i64, f64)> = conn
+            .prepare("select id, price from prices order by id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        assert_eq!(prices, vec![(1, 2.5), (2, 3.5)]);
+
+        assert_eq!(
+            std::fs::read_to_string(upsert_csv).unwrap(),
+            "table,inserted,updated,skipped\nprices,1,1,0\ncodes,1,0,1\n"
+        );
+    }
+
+    #[test]
+    fn test_csvs_enums_to_sqlite() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+
+        csvs_to_sqlite_with_options(
+            tmp.join("sqlite.db").to_string_lossy().into(),
+            vec!["fixtures/enums.csv".into()],
+            Options::builder().detect_enums(true).build(),
+        )
+        .unwrap();
+
+        let conn = Connection::open(tmp.join("sqlite.db")).unwrap();
+        let sql: String = conn
+            .query_row(
+                "select sql from sqlite_master where name = 'enums'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(sql.contains("CHECK ([status] IN ('closed', 'open', 'won''t fix'))"));
+        assert!(
+            conn.execute("insert into enums values (9, 'reopened', 'ninth')", [])
+                .is_err()
+        );
+
+        std::fs::write(tmp.join("enums.csv"), "id,status,note\n9,reopened,ninth\n").unwrap();
+        csvs_to_sqlite_with_options(
+            tmp.join("sqlite.db").to_string_lossy().into(),
+            vec![tmp.join("enums.csv")],
+            Options::builder().evolve(true).build(),
+        )
+        .unwrap();
+        let sql: String = conn
+            .query_row(
+                "select sql from sqlite_master where name = 'enums'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(!sql.contains("IN ("));
+        let count: i64 = conn
+            .query_row("select count(*) from enums", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 9);
+    }
+
+    #[test]
+    fn test_evolve_constraints_postgres() {
+        let db_url = "postgresql://test@localhost/test";
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+
+        std::fs::write(tmp.join("ranges.csv"), "id,score\n1,3\n2,5\n").unwrap();
+        csvs_to_postgres_with_options(
+            db_url.into(),
+            vec![tmp.join("ranges.csv")],
+            Options::builder()
+                .drop(true)
+                .constraints(true)
+                .schema("test_evolve_constraints".into())
+                .build(),
+        )
+        .unwrap();
+
+        std::fs::write(tmp.join("ranges.csv"), "id,score\n2,high\n").unwrap();
+        csvs_to_postgres_with_options(
+            db_url.into(),
+            vec![tmp.join("ranges.csv")],
+            Options::builder()
+                .evolve(true)
+                .schema("test_evolve_constraints".into())
+                .build(),
+        )
+        .unwrap();
+
+        let mut client = Client::connect(db_url, NoTls).unwrap();
+        let scores: Vec<String> = client
+            .query(
+                "select score from test_evolve_constraints.ranges order by score",
+                &[],
+            )
+            .unwrap()
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        assert_eq!(scores, vec!["3", "5", "high"]);
+    }
+
+    #[test]
+    fn test_evolve_enums_postgres() {
+        let db_url = "postgresql://test@localhost/test";
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+
+        csvs_to_postgres_with_options(
+            db_url.into(),
+            vec!["fixtures/enums.csv".into()],
+            Options::builder()
+                .drop(true)
+                .detect_enums(true)
+                .schema("test_evolve_enums".into())
+                .build(),
+        )
+        .unwrap();
+
+        std::fs::write(tmp.join("enums.csv"), "id,status,note\n9,reopened,ninth\n").unwrap();
+        csvs_to_postgres_with_options(
+            db_url.into(),
+            vec![tmp.join("enums.csv")],
+            Options::builder()
+                .evolve(true)
+                .schema("test_evolve_enums".into())
+                .build(),
+        )
+        .unwrap();
+
+        let mut client = Client::connect(db_url, NoTls).unwrap();
+        let count: i64 = client
+            .query_one("select count(*) from test_evolve_enums.enums", &[])
+            .unwrap()
+            .get(0);
+        assert_eq!(count, 9);
+    }
+
+    #[test]
+    fn test_csvs_constraints_to_sqlite() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+
+        csvs_to_sqlite_with_options(
+            tmp.join("sqlite.db").to_string_lossy().into(),
+            vec!["fixtures/keys.csv".into()],
+            Options::builder()
+                .constraints(true)
+                .unique_constraints(true)
+                .build(),
+        )
+        .unwrap();
+
+        let conn = Connection::open(tmp.join("sqlite.db")).unwrap();
+        let sql: String = conn
+            .query_row(
+                "select sql from sqlite_master where name = 'keys'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        insta::assert_snapshot!(sql);
+        let count: i64 = conn
+            .query_row("select count(*) from keys", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 4);
+        for insert in [
+            "insert into keys values (5, 'a5', 'uk', 2019, 2)",
+            "insert into keys values (1, 'a6', 'uk', 2020, 2)",
+            "insert into keys values (6, 'a66', 'uk', 2020, 2)",
+            "insert into keys values (7, 'a7', null, 2020, 2)",
+            "insert into keys values (8, 'a8', 'uk', 2020, 3.5)",
+        ] {
+            assert!(conn.execute(insert, []).is_err(), "{insert}");
+        }
+        conn.execute("delete from keys where id = 4", []).unwrap();
+        conn.execute("insert into keys values (4, 'a9', 'de', 2021, 2.5)", [])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_enums_to_xlsx_and_ods() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+
+        let read_zip_file = |path: PathBuf, name: &str| {
+            let mut archive = zip::ZipArchive::new(File::open(path).unwrap()).unwrap();
+            let mut content = String::new();
+            std::io::Read::read_to_string(&mut archive.by_name(name).unwrap(), &mut content)
+                .unwrap();
+            content
+        };
+
+        csvs_to_xlsx_with_options(
+            tmp.join("output.xlsx").to_string_lossy().into(),
+            vec!["fixtures/enums.csv".into()],
+            Options::builder().detect_enums(true).build(),
+        )
+        .unwrap();
+        let sheet = read_zip_file(tmp.join("output.xlsx"), "xl/worksheets/sheet1.xml");
+        assert!(sheet.contains(r#"<dataValidation type="list""#));
+        assert!(sheet.contains("sqref=\"B2:B9\""));
+
+        csvs_to_ods_with_options(
+            tmp.join("output.ods").to_string_lossy().into(),
+            vec!["fixtures/enums.csv".into()],
+            Options::builder().detect_enums(true).build(),
+        )
+        .unwrap();
+        let content = read_zip_file(tmp.join("output.ods"), "content.xml");
+        assert!(content.contains("cell-content-is-in-list"));
+    }
+
+    #[test]
+    fn test_csvs_invalid_values_to_sqlite() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+        let rejects_csv = tmp.join("rejects.csv");
+
+        csvs_to_sqlite_with_options(
+            tmp.join("sqlite.db").to_string_lossy().into(),
+            vec!["fixtures/invalid_values.csv".into()],
+            Options::builder()
+                .max_invalid_ratio(0.2)
+                .rejects_csv(rejects_csv.to_string_lossy().into())
+                .build(),
+        )
+        .unwrap();
+
+        let conn = Connection::open(tmp.join("sqlite.db")).unwrap();
+        let mut stmt = conn
+            .prepare("select amount, day from invalid_values where id in (4, 5, 7)")
+            .unwrap();
+        let mut rows = stmt.query([]).unwrap();
+
+        let mut output: Vec<(Option<i64>, Option<String>)> = vec![];
+        while let Some(row) = rows.next().unwrap() {
+            output.push((row.get(0).unwrap(), row.get(1).unwrap()));
+        }
+        assert_eq!(
+            output,
+            vec![
+                (None, Some("2024-01-04".into())),
+                (Some(50), Some("2024-01-05".into())),
+                (Some(70), None)
+            ]
+        );
+
+        assert_eq!(
+            std::fs::read_to_string(rejects_csv).unwrap(),
+            "table,row,field,value\ninvalid_values,4,amount,n/a\ninvalid_values,7,day,unknown\n"
+        );
+    }
+
+    #[derive(Debug)]
+    struct FiscalPeriod;
+
+    impl crate::describer::Detector for FiscalPeriod {
+        fn type_name(&self) -> &str {
+            "string"
+        }
+        fn format(&self) -> &str {
+            "fiscal_period"
+        }
+        fn check(&self, value: &str) -> bool {
+            regex::Regex::new(r"^FY[0-9]{4}-Q[1-4]$")
+                .unwrap()
+                .is_match(value)
+        }
+    }
+
+    #[test]
+    fn test_csvs_custom_detector_to_sqlite() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+        let rejects_csv = tmp.join("rejects.csv");
+
+        let datapackage = csvs_to_sqlite_with_options(
+            tmp.join("sqlite.db").to_string_lossy().into(),
+            vec!["fixtures/fiscal_periods.csv".into()],
+            Options::builder()
+                .max_invalid_ratio(0.2)
+                .detectors(vec![Arc::new(FiscalPeriod)])
+                .rejects_csv(rejects_csv.to_string_lossy().into())
+                .build(),
+        )
+        .unwrap();
+        let field = &datapackage["resources"][0]["schema"]["fields"][1];
+        assert_eq!(field["type"], "string");
+        assert_eq!(field["format"], "fiscal_period");
+
+        assert_eq!(
+            std::fs::read_to_string(rejects_csv).unwrap(),
+            "table,row,field,value\nfiscal_periods,10,period,not known\n"
+        );
+    }
+
+    #[test]
+    fn test_csvs_dates_to_sqlite() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+
+        csvs_to_sqlite_with_options(
+            tmp.join("sqlite.db").to_string_lossy().into(),
+            vec!["fixtures/day_first.csv".into()],
+            Options::builder()
+                .date_order(Some(DateOrder::Dmy))
+                .time_formats(vec!["%H:%M:%S".into()])
+                .build(),
+        )
+        .unwrap();
+
+        let conn = Connection::open(tmp.join("sqlite.db")).unwrap();
+        let mut stmt = conn
+            .prepare("select date, time, date < '2024-03-01' from day_first")
+            .unwrap();
+        let mut rows = stmt.query([]).unwrap();
+
+        let mut output: Vec<(String, String, bool)> = vec![];
+        while let Some(row) = rows.next().unwrap() {
+            output.push((
+                row.get(0).unwrap(),
+                row.get(1).unwrap(),
+                row.get(2).unwrap(),
+            ));
+        }
+        assert_eq!(
+            output,
+            vec![
+                ("2024-02-01".into(), "12:30:01".into(), true),
+                ("2024-04-03".into(), "13:45:59".into(), false)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_csvs_typed_values_to_sqlite() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+
+        csvs_to_sqlite(
+            tmp.join("sqlite.db").to_string_lossy().into(),
+            vec!["fixtures/typed_values.csv".into()],
+        )
+        .unwrap();
+
+        let conn = Connection::open(tmp.join("sqlite.db")).unwrap();
+        let mut stmt = conn
+            .prepare(
+                "select typeof(id), typeof(amount), typeof(active), typeof(name), typeof(joined) from typed_values",
+            )
+            .unwrap();
+        let mut rows = stmt.query([]).unwrap();
+
+        let mut output: Vec<Vec<String>> = vec![];
+        while let Some(row) = rows.next().unwrap() {
+            output.push((0..5).map(|i| row.get(i).unwrap()).collect());
+        }
+        assert_eq!(
+            output,
+            vec![
+                vec!["integer", "real", "integer", "text", "text"],
+                vec!["integer", "real", "integer", "null", "text"]
+            ]
+        );
+
+        let totals: (f64, i64) = conn
+            .query_row(
+                "select sum(amount), sum(active) from typed_values",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(totals, (3.75, 1));
+    }
+
+    #[test]
+    fn test_csvs_precise_numbers_to_sqlite() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+
+        for strict in [false, true] {
+            let db = tmp.join(format!("sqlite-{strict}.db"));
+            let options = Options::builder().strict(strict).build();
+            csvs_to_sqlite_with_options(
+                db.to_string_lossy().into(),
+                vec!["fixtures/precise_numbers.csv".into()],
+                options,
+            )
+            .unwrap();
+
+            let conn = Connection::open(db).unwrap();
+            let values: (String, String, f64) = conn
+                .query_row(
+                    "select amount, typeof(amount), total from precise_numbers where id = 1",
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                )
+                .unwrap();
+            assert_eq!(values, ("1234567890123.4567891".into(), "text".into(), 1.5));
+        }
+    }
+
+    #[test]
+    fn test_csvs_strict_indexes_to_sqlite() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+
+        csvs_to_sqlite_with_options(
+            tmp.join("sqlite.db").to_string_lossy().into(),
+            vec!["fixtures/typed_values.csv".into()],
+            Options::builder()
+                .strict(true)
+                .indexes(HashMap::from([(
+                    "typed_values".into(),
+                    vec![vec!["name".into()], vec!["joined".into(), "id".into()]],
+                )]))
+                .build(),
+        )
+        .unwrap();
+
+        let conn = Connection::open(tmp.join("sqlite.db")).unwrap();
+        let sql: String = conn
+            .query_row(
+                "select sql from sqlite_schema where name = 'typed_values'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(sql.ends_with(") STRICT"));
+        assert!(sql.contains("[amount] REAL"));
+        assert!(sql.contains("[joined] TEXT"));
+
+        let mut stmt = conn
+            .prepare("select name from pragma_index_list('typed_values') order by name")
+            .unwrap();
+        let indexes: Vec<String> = stmt
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .map(|name| name.unwrap())
+            .collect();
+        assert_eq!(
+            indexes,
+            vec!["idx_typed_values_joined_id", "idx_typed_values_name"]
+        );
+
+        let analyzed: i64 = conn
+            .query_row(
+                "select count(*) from sqlite_stat1 where tbl = 'typed_values'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(analyzed, 2);
+    }
+
+    #[test]
+    fn test_csvs_locale_numbers_to_sqlite() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+
+        let datapackage = csvs_to_sqlite_with_options(
+            tmp.join("sqlite.db").to_string_lossy().into(),
+            vec!["fixtures/locale_numbers.csv".into()],
+            Options::builder()
+                .decimal_char(Some(','))
+                .group_char(Some('.'))
+                .bare_number(false)
+                .build(),
+        )
+        .unwrap();
+        insta::assert_yaml_snapshot!(datapackage["resources"][0]["schema"]);
+
+        let conn = Connection::open(tmp.join("sqlite.db")).unwrap();
+        let mut stmt = conn
+            .prepare("select price, share, count from locale_numbers")
+            .unwrap();
+        let mut rows = stmt.query([]).unwrap();
+
+        let mut output: Vec<(f64, f64, i64)> = vec![];
+        while let Some(row) = rows.next().unwrap() {
+            output.push((
+                row.get(0).unwrap(),
+                row.get(1).unwrap(),
+                row.get(2).unwrap(),
+            ));
+        }
+        assert_eq!(
+            output,
+            vec![
+                (1234.56, 45.0, 1000),
+                (12.5, 3.5, 25),
+                (-0.75, 100.0, 2500000)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_csvs_sniffed_dialect_to_sqlite() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+
+        let datapackage = csvs_to_sqlite_with_options(
+            tmp.join("sqlite.db").to_string_lossy().into(),
+            vec!["fixtures/sniff.csv".into()],
+            Options::builder().build(),
+        )
+        .unwrap();
+        insta::assert_yaml_snapshot!(datapackage["resources"][0]["dialect"]);
+
+        let conn = Connection::open(tmp.join("sqlite.db")).unwrap();
+        let mut stmt = conn.prepare("select id, note from sniff").unwrap();
+        let mut rows = stmt.query([]).unwrap();
+
+        let mut output: Vec<(i64, String)> = vec![];
+        while let Some(row) = rows.next().unwrap() {
+            output.push((row.get(0).unwrap(), row.get(1).unwrap()));
+        }
+        assert_eq!(output, vec![(1, "a, b".into()), (2, "say \"hi\"".into())]);
+    }
+
+    #[test]
+    fn test_csvs_all_types_to_sqlite() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+
+        csvs_to_sqlite_with_options(
+            tmp.join("sqlite.db").to_string_lossy().into(),
+            vec![
+                "src/fixtures/all_types.csv".into(),
+                "src/fixtures/all_types_semi_colon.csv".into(),
+            ],
+            Options::builder().stats(true).build(),
+        )
+        .unwrap();
+
+        assert!(tmp.join("sqlite.db").exists());
+    }
+
+    #[test]
+    fn test_csvs_to_sqlite_large() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+
+        let options = Options::builder()
+            .stats(true)
+            .threads(8)
+            .delimiter(Some(b','));
+
+        csvs_to_sqlite_with_options(
+            tmp.join("sqlite.db").to_string_lossy().into(),
+            vec![
+                "fixtures/large/csv/data.csv".into(),
+                "fixtures/large/csv/daily_16.csv".into(),
+                "fixtures/large/csv/data_weather.csv".into(),
+            ],
+            options.build(),
+        )
+        .unwrap();
+
+        assert!(tmp.join("sqlite.db").exists());
+    }
+
+    #[test]
+    fn test_ods_from_csvs() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+        //let tmp = PathBuf::from("/tmp");
+
+        csvs_to_ods(
+            tmp.join("output.ods").to_string_lossy().into(),
+            vec![
+                "src/fixtures/all_types.csv".into(),
+                "src/fixtures/all_types_semi_colon.csv".into(),
+            ],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_xlsx_from_csvs() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+        // let tmp = PathBuf::from("/tmp");
+
+        csvs_to_xlsx(
+            tmp.join("output.xlsx").to_string_lossy().into(),
+            vec![
+                "src/fixtures/all_types.csv".into(),
+                "src/fixtures/all_types_semi_colon.csv".into(),
+            ],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_large_ods_from_csvs() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+        // let tmp = PathBuf::from("/tmp");
+
+        csvs_to_ods(
             tmp.join("output.ods").to_string_lossy().into(),
             vec![
                 "fixtures/large/csv/data.csv".into(),
@@ -2861,6 +6029,70 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_merge_constraints() {
+        let merged = |base: Value, field: Value| {
+            let mut base = base.as_object().unwrap().clone();
+            merge_constraints(&mut base, &field);
+            Value::from(base)
+        };
+
+        assert_eq!(
+            merged(
+                json!({"constraints": {"enum": ["a", "b"]}}),
+                json!({"constraints": {"enum": ["b", "c"]}})
+            ),
+            json!({"constraints": {"enum": ["a", "b", "c"]}})
+        );
+        assert_eq!(
+            merged(json!({"constraints": {"enum": ["a"]}}), json!({})),
+            json!({})
+        );
+        assert_eq!(
+            merged(
+                json!({
+                    "type": "integer",
+                    "constraints": {"required": true, "unique": true, "minimum": 1, "maximum": 5}
+                }),
+                json!({"constraints": {"required": true, "minimum": -2, "maximum": 3}})
+            ),
+            json!({
+                "type": "integer",
+                "constraints": {"required": true, "minimum": -2, "maximum": 5}
+            })
+        );
+        assert_eq!(
+            merged(
+                json!({"type": "string", "constraints": {"minimum": 1, "maximum": 5}}),
+                json!({"constraints": {"minimum": 2, "maximum": 3}})
+            ),
+            json!({"type": "string"})
+        );
+        assert_eq!(
+            merged(
+                json!({"type": "string", "constraints": {"minLength": 2, "maxLength": 4}}),
+                json!({"constraints": {"minLength": 1, "maxLength": 9}})
+            ),
+            json!({"type": "string", "constraints": {"minLength": 1, "maxLength": 9}})
+        );
+        assert_eq!(
+            merged(
+                json!({"type": "string", "constraints": {"maxLength": 4}}),
+                json!({})
+            ),
+            json!({"type": "string"})
+        );
+
+        let many: Vec<String> = (0..MAX_ENUM_VALUES).map(|i| i.to_string()).collect();
+        assert_eq!(
+            merged(
+                json!({"constraints": {"enum": many}}),
+                json!({"constraints": {"enum": ["x"]}})
+            ),
+            json!({})
+        );
+    }
+
     #[test]
     fn test_csvs_db() {
         let options = Options::builder().drop(true).schema("test".into()).build();
@@ -2893,10 +6125,13 @@ mod tests {
         )
         .unwrap();
 
+        // Dumps refer to files by their full path.
+        let cwd = std::env::current_dir().unwrap();
+        let cwd = cwd.to_string_lossy();
         let file = File::open("/tmp/postgres_dump.sql").unwrap();
         let lines: Vec<String> = std::io::BufReader::new(file)
             .lines()
-            .map(|x| x.unwrap())
+            .map(|x| x.unwrap().replace(&*cwd, "[cwd]"))
             .collect();
         insta::assert_yaml_snapshot!(lines);
     }
@@ -2933,87 +6168,542 @@ mod tests {
     }
 
     #[test]
-    fn test_from_env() {
-        temp_env::with_var(
-            "POSTGRES_URL",
-            Some("postgresql://test@localhost/test"),
-            || {
-                let options = Options::builder()
-                    .drop(true)
-                    .schema("test_env".into())
-                    .build();
-
-                datapackage_to_postgres_with_options(
-                    " env= POSTGRES_URL ".into(),
-                    "fixtures/large".into(),
-                    options,
-                )
-                .unwrap();
-            },
+    fn test_from_env() {
+        temp_env::with_var(
+            "POSTGRES_URL",
+            Some("postgresql://test@localhost/test"),
+            || {
+                let options = Options::builder()
+                    .drop(true)
+                    .schema("test_env".into())
+                    .build();
+
+                datapackage_to_postgres_with_options(
+                    " env= POSTGRES_URL ".into(),
+                    "fixtures/large".into(),
+                    options,
+                )
+                .unwrap();
+            },
+        );
+
+        temp_env::with_var(
+            "DATABASE_URL",
+            Some("postgresql://test@localhost/test"),
+            || {
+                let options = Options::builder()
+                    .drop(true)
+                    .schema("test_env".into())
+                    .build();
+
+                datapackage_to_postgres_with_options(
+                    " env  ".into(),
+                    "fixtures/large".into(),
+                    options,
+                )
+                .unwrap();
+            },
+        );
+    }
+
+    // #[test]
+    // fn test_postgres_types() {
+    //     let options = Options::builder()
+    //         .drop(true)
+    //         .schema("types".into())
+    //         .build();
+
+    //     csvs_to_postgres_with_options("postgresql://test@localhost/test".into(), vec!["src/fixtures/date.csv".into()], options).unwrap();
+    // }
+
+    #[test]
+    fn test_drop_postgres() {
+        let options = Options::builder()
+            .drop(true)
+            .schema("test_drop2".into())
+            .build();
+
+        datapackage_to_postgres_with_options(
+            "postgresql://test@localhost/test".into(),
+            "fixtures/large".into(),
+            options,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_truncate_postgres() {
+        let options = Options::builder()
+            .truncate(true)
+            .schema("test_truncate".into())
+            .build();
+
+        datapackage_to_postgres_with_options(
+            "postgresql://test@localhost/test".into(),
+            "fixtures/large".into(),
+            options.clone(),
+        )
+        .unwrap();
+
+        datapackage_to_postgres_with_options(
+            "postgresql://test@localhost/test".into(),
+            "fixtures/large".into(),
+            options,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_upsert_postgres() {
+        let db_url = "postgresql://test@localhost/test";
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+
+        csvs_to_postgres_with_options(
+            db_url.into(),
+            vec!["fixtures/typed_values.csv".into()],
+            Options::builder()
+                .drop(true)
+                .detect_keys(true)
+                .schema("test_upsert".into())
+                .build(),
+        )
+        .unwrap();
+
+        std::fs::write(
+            tmp.join("typed_values.csv"),
+            "id,amount,active,name,joined\n2,7.5,true,b,2024-02-03\n3,1.25,false,c,2024-03-04\n",
+        )
+        .unwrap();
+
+        let upsert_csv = tmp.join("upserts.csv");
+        csvs_to_postgres_with_options(
+            db_url.into(),
+            vec![tmp.join("typed_values.csv")],
+            Options::builder()
+                .detect_keys(true)
+                .upsert(true)
+                .upsert_csv(upsert_csv.to_string_lossy().into())
+                .schema("test_upsert".into())
+                .build(),
+        )
+        .unwrap();
+
+        let mut client = Client::connect(db_url, NoTls).unwrap();
+        let amounts: Vec<String> = client
+            .query(
+                "select amount::text from test_upsert.typed_values order by id",
+                &[],
+            )
+            .unwrap()
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        assert_eq!(amounts, vec!["1.50", "7.50", "1.25"]);
+
+        assert_eq!(
+            std::fs::read_to_string(upsert_csv).unwrap(),
+            "table,inserted,updated,skipped\ntyped_values,1,1,0\n"
+        );
+    }
+
+    #[test]
+    fn test_upsert_duplicate_keys_postgres() {
+        let db_url = "postgresql://test@localhost/test";
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+
+        let mut client = Client::connect(db_url, NoTls).unwrap();
+        client
+            .batch_execute(
+                "DROP SCHEMA IF EXISTS test_upsert_duplicates CASCADE;
+                 CREATE SCHEMA test_upsert_duplicates;
+                 CREATE TABLE test_upsert_duplicates.prices (id BIGINT PRIMARY KEY, price NUMERIC);
+                 INSERT INTO test_upsert_duplicates.prices VALUES (1, 1.5);",
+            )
+            .unwrap();
+
+        // The resource has no primary key so the table's key is used and the
+        // last row for each key wins.
+        std::fs::write(tmp.join("prices.csv"), "id,price\n1,2.5\n2,3.5\n1,4.5\n").unwrap();
+        let upsert_csv = tmp.join("upserts.csv");
+        csvs_to_postgres_with_options(
+            db_url.into(),
+            vec![tmp.join("prices.csv")],
+            Options::builder()
+                .upsert(true)
+                .upsert_csv(upsert_csv.to_string_lossy().into())
+                .schema("test_upsert_duplicates".into())
+                .build(),
+        )
+        .unwrap();
+
+        let prices: Vec<String> = client
+            .query(
+                "select price::text from test_upsert_duplicates.prices order by id",
+                &[],
+            )
+            .unwrap()
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        assert_eq!(prices, vec!["4.5", "3.5"]);
+
+        assert_eq!(
+            std::fs::read_to_string(upsert_csv).unwrap(),
+            "table,inserted,updated,skipped\nprices,1,1,1\n"
+        );
+    }
+
+    fn write_orders_datapackage(dir: &Path, customer_id: &str) {
+        std::fs::write(dir.join("customers.csv"), "id,name\n1,a\n2,b\n").unwrap();
+        std::fs::write(
+            dir.join("orders.csv"),
+            format!("id,customer_id\n1,1\n2,{customer_id}\n"),
+        )
+        .unwrap();
+        let datapackage = json!({
+            "resources": [
+                {
+                    "name": "customers",
+                    "path": "customers.csv",
+                    "schema": {
+                        "fields": [
+                            {"name": "id", "type": "integer"},
+                            {"name": "name", "type": "string"}
+                        ],
+                        "primaryKey": "id"
+                    }
+                },
+                {
+                    "name": "orders",
+                    "path": "orders.csv",
+                    "schema": {
+                        "fields": [
+                            {"name": "id", "type": "integer"},
+                            {"name": "customer_id", "type": "integer"}
+                        ],
+                        "foreignKeys": [
+                            {"fields": "customer_id", "reference": {"resource": "customers", "fields": "id"}}
+                        ]
+                    }
+                }
+            ]
+        });
+        std::fs::write(
+            dir.join("datapackage.json"),
+            serde_json::to_string(&datapackage).unwrap(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_transaction_postgres() {
+        let db_url = "postgresql://test@localhost/test";
+        let mut client = Client::connect(db_url, NoTls).unwrap();
+        client
+            .batch_execute("DROP SCHEMA IF EXISTS test_transaction CASCADE")
+            .unwrap();
+
+        let tmp_dir = TempDir::new().unwrap();
+        write_orders_datapackage(tmp_dir.path(), "9");
+
+        let result = datapackage_to_postgres_with_options(
+            db_url.into(),
+            tmp_dir.path().to_string_lossy().into(),
+            Options::builder()
+                .transaction(true)
+                .schema("test_transaction".into())
+                .build(),
+        );
+        assert!(result.is_err());
+
+        let customers: Option<String> = client
+            .query_one(
+                "SELECT to_regclass('test_transaction.customers')::TEXT",
+                &[],
+            )
+            .unwrap()
+            .get(0);
+        assert!(customers.is_none());
+    }
+
+    #[test]
+    fn test_swap_schema_keeping_rows_postgres() {
+        let tmp_dir = TempDir::new().unwrap();
+        write_orders_datapackage(tmp_dir.path(), "2");
+        let keep_rows: [fn(&mut Options); 3] = [
+            |options| options.upsert = true,
+            |options| options.evolve = true,
+            |options| options.truncate = true,
+        ];
+        for keep_rows in keep_rows {
+            let mut options = Options::builder()
+                .swap_schema(true)
+                .schema("test_swap_keeping_rows".into())
+                .build();
+            keep_rows(&mut options);
+            let result = datapackage_to_postgres_with_options(
+                "postgresql://test@localhost/test".into(),
+                tmp_dir.path().to_string_lossy().into(),
+                options,
+            );
+            assert!(
+                result
+                    .unwrap_err()
+                    .to_string()
+                    .contains("can not be used with `upsert`")
+            );
+        }
+    }
+
+    #[test]
+    fn test_swap_schema_postgres() {
+        let db_url = "postgresql://test@localhost/test";
+        let options = Options::builder()
+            .swap_schema(true)
+            .schema("test_swap".into())
+            .build();
+
+        let tmp_dir = TempDir::new().unwrap();
+        write_orders_datapackage(tmp_dir.path(), "2");
+        datapackage_to_postgres_with_options(
+            db_url.into(),
+            tmp_dir.path().to_string_lossy().into(),
+            options.clone(),
+        )
+        .unwrap();
+
+        write_orders_datapackage(tmp_dir.path(), "9");
+        let result = datapackage_to_postgres_with_options(
+            db_url.into(),
+            tmp_dir.path().to_string_lossy().into(),
+            options.clone(),
+        );
+        assert!(result.is_err());
+
+        let mut client = Client::connect(db_url, NoTls).unwrap();
+        let customer_ids: Vec<i64> = client
+            .query("SELECT customer_id FROM test_swap.orders ORDER BY id", &[])
+            .unwrap()
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        assert_eq!(customer_ids, vec![1, 2]);
+
+        let schemas: i64 = client
+            .query_one(
+                "SELECT count(*) FROM pg_namespace WHERE nspname LIKE 'test_swap_%'",
+                &[],
+            )
+            .unwrap()
+            .get(0);
+        assert_eq!(schemas, 0);
+
+        write_orders_datapackage(tmp_dir.path(), "1");
+        datapackage_to_postgres_with_options(
+            db_url.into(),
+            tmp_dir.path().to_string_lossy().into(),
+            options,
+        )
+        .unwrap();
+
+        let customer_ids: Vec<i64> = client
+            .query("SELECT customer_id FROM test_swap.orders ORDER BY id", &[])
+            .unwrap()
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        assert_eq!(customer_ids, vec![1, 1]);
+    }
+
+    #[test]
+    fn test_dump_inline_postgres() {
+        let db_url = "postgresql://test@localhost/test";
+        let tmp_dir = TempDir::new().unwrap();
+        let dump_file = tmp_dir.path().join("dump.sql");
+
+        csvs_to_postgres_with_options(
+            "".into(),
+            vec!["fixtures/typed_values.csv".into()],
+            Options::builder()
+                .schema("test_dump_inline".into())
+                .dump_file(dump_file.to_string_lossy().into())
+                .dump_inline(true)
+                .build(),
+        )
+        .unwrap();
+
+        let dump = std::fs::read_to_string(&dump_file).unwrap();
+        assert!(!dump.contains("\\copy"));
+
+        // Replay the dump the way psql would, sending each `FROM STDIN` block
+        // through COPY.
+        let mut client = Client::connect(db_url, NoTls).unwrap();
+        client
+            .batch_execute("DROP SCHEMA IF EXISTS test_dump_inline CASCADE;")
+            .unwrap();
+        let mut statements = String::new();
+        let mut lines = dump.lines();
+        while let Some(line) = lines.next() {
+            if !line.starts_with("copy ") {
+                statements.push_str(line);
+                statements.push('\n');
+                continue;
+            }
+            client.batch_execute(&statements).unwrap();
+            statements.clear();
+            let mut writer = client.copy_in(line.trim_end_matches(';')).unwrap();
+            for data in lines.by_ref().take_while(|data| *data != "\\.") {
+                writer.write_all(format!("{data}\n").as_bytes()).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+        client.batch_execute(&statements).unwrap();
+
+        let rows: Vec<(i64, Option<String>)> = client
+            .query(
+                "SELECT id::bigint, name FROM test_dump_inline.typed_values ORDER BY id",
+                &[],
+            )
+            .unwrap()
+            .iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect();
+        assert_eq!(rows, vec![(1, Some("a".into())), (2, None)]);
+    }
+
+    #[test]
+    fn test_dump_encoding_postgres() {
+        let db_url = "postgresql://test@localhost/test";
+        let tmp_dir = TempDir::new().unwrap();
+        let dump_file = tmp_dir.path().join("dump.sql");
+
+        csvs_to_postgres_with_options(
+            "".into(),
+            vec!["fixtures/windows_1252.csv".into()],
+            Options::builder()
+                .schema("test_dump_encoding".into())
+                .dump_file(dump_file.to_string_lossy().into())
+                .build(),
+        )
+        .unwrap();
+
+        let dump = std::fs::read_to_string(&dump_file).unwrap();
+        let (statements, copy) = dump.split_once("\\copy ").unwrap();
+        assert!(copy.contains("ENCODING 'windows-1252', "));
+
+        // Send the file as psql would for `\copy`.
+        let mut client = Client::connect(db_url, NoTls).unwrap();
+        client
+            .batch_execute("DROP SCHEMA IF EXISTS test_dump_encoding CASCADE;")
+            .unwrap();
+        client.batch_execute(statements).unwrap();
+        let (table, options) = copy.trim().split_once(" from ").unwrap();
+        let (_, options) = options.split_once(" WITH ").unwrap();
+        let mut writer = client
+            .copy_in(&format!("copy {table} from STDIN WITH {options}"))
+            .unwrap();
+        writer
+            .write_all(&std::fs::read("fixtures/windows_1252.csv").unwrap())
+            .unwrap();
+        writer.finish().unwrap();
+
+        let names: Vec<String> = client
+            .query("SELECT name FROM test_dump_encoding.windows_1252", &[])
+            .unwrap()
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        assert_eq!(names, vec!["José", "François", "Zoë"]);
+    }
+
+    #[test]
+    fn test_split_ssl_settings() {
+        let (conf, settings) = split_ssl_settings(
+            "postgresql://test@localhost/test?sslmode=verify-full&connect_timeout=10&sslrootcert=%2Fcerts%2Froot.crt",
         );
+        assert_eq!(conf, "postgresql://test@localhost/test?connect_timeout=10");
+        assert_eq!(settings.mode, "verify-full");
+        assert_eq!(settings.root_cert, "/certs/root.crt");
 
-        temp_env::with_var(
-            "DATABASE_URL",
-            Some("postgresql://test@localhost/test"),
-            || {
-                let options = Options::builder()
-                    .drop(true)
-                    .schema("test_env".into())
-                    .build();
+        let (conf, settings) =
+            split_ssl_settings("postgresql://test@localhost/test?sslmode=require");
+        assert_eq!(conf, "postgresql://test@localhost/test");
+        assert_eq!(settings.mode, "require");
 
-                datapackage_to_postgres_with_options(
-                    " env  ".into(),
-                    "fixtures/large".into(),
-                    options,
-                )
-                .unwrap();
-            },
+        let (conf, settings) = split_ssl_settings(
+            "host=localhost password='it\\'s secret' sslmode = verify-ca sslcert='/my certs/client.crt' sslkey=/certs/client.key",
+        );
+        assert_eq!(conf, "host='localhost' password='it\\'s secret'");
+        assert_eq!(
+            settings,
+            SslSettings {
+                mode: "verify-ca".into(),
+                root_cert: "".into(),
+                cert: "/my certs/client.crt".into(),
+                key: "/certs/client.key".into(),
+            }
         );
     }
 
-    // #[test]
-    // fn test_postgres_types() {
-    //     let options = Options::builder()
-    //         .drop(true)
-    //         .schema("types".into())
-    //         .build();
-
-    //     csvs_to_postgres_with_options("postgresql://test@localhost/test".into(), vec!["src/fixtures/date.csv".into()], options).unwrap();
-    // }
-
+    #[cfg(not(feature = "tls"))]
     #[test]
-    fn test_drop_postgres() {
-        let options = Options::builder()
-            .drop(true)
-            .schema("test_drop2".into())
-            .build();
-
-        datapackage_to_postgres_with_options(
-            "postgresql://test@localhost/test".into(),
-            "fixtures/large".into(),
-            options,
-        )
-        .unwrap();
+    fn test_postgres_tls_needs_feature() {
+        let result = connect_postgres(
+            "postgresql://test@localhost/test?sslmode=require",
+            &Options::builder().build(),
+        );
+        assert!(matches!(
+            result,
+            Err(Error::DatapackageConvert { message }) if message.contains("`tls` feature")
+        ));
     }
 
+    // Needs postgres to use fixtures/tls/server.crt and server.key as its
+    // `ssl_cert_file` and `ssl_key_file` and fixtures/tls/root.crt as its
+    // `ssl_ca_file`.
+    #[cfg(feature = "tls")]
     #[test]
-    fn test_truncate_postgres() {
+    fn test_tls_postgres() {
         let options = Options::builder()
-            .truncate(true)
-            .schema("test_truncate".into())
+            .ssl_cert("fixtures/tls/client.crt".into())
+            .ssl_key("fixtures/tls/client.key".into())
             .build();
-
-        datapackage_to_postgres_with_options(
-            "postgresql://test@localhost/test".into(),
-            "fixtures/large".into(),
-            options.clone(),
+        let mut client = connect_postgres(
+            "postgresql://test@localhost/test?sslmode=verify-full&sslrootcert=fixtures/tls/root.crt",
+            &options,
         )
         .unwrap();
+        let row = client
+            .query_one(
+                "select ssl, client_dn from pg_stat_ssl where pid = pg_backend_pid()",
+                &[],
+            )
+            .unwrap();
+        let ssl: bool = row.get(0);
+        let client_dn: String = row.get(1);
+        assert!(ssl);
+        assert_eq!(client_dn, "/CN=test");
+
+        // The test root is not trusted by the system.
+        assert!(
+            connect_postgres(
+                "host=localhost user=test dbname=test sslmode=verify-full",
+                &Options::builder().build()
+            )
+            .is_err()
+        );
 
         datapackage_to_postgres_with_options(
             "postgresql://test@localhost/test".into(),
-            "fixtures/large".into(),
-            options,
+            "fixtures/evolve/base".into(),
+            Options::builder()
+                .drop(true)
+                .schema("test_tls".into())
+                .ssl_mode("verify-ca".into())
+                .ssl_root_cert("fixtures/tls/root.crt".into())
+                .build(),
         )
         .unwrap();
     }
@@ -3151,6 +6841,228 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn test_dump_inline_sqlite() {
+        let tmp_dir = TempDir::new().unwrap();
+        let dump_file = tmp_dir.path().join("dump.sql");
+
+        csvs_to_sqlite_with_options(
+            "".into(),
+            vec!["fixtures/typed_values.csv".into()],
+            Options::builder()
+                .dump_file(dump_file.to_string_lossy().into())
+                .dump_inline(true)
+                .build(),
+        )
+        .unwrap();
+
+        let dump = std::fs::read_to_string(&dump_file).unwrap();
+        assert!(!dump.contains(".import"));
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(&dump).unwrap();
+        let mut stmt = conn
+            .prepare("select id, amount, active, name from typed_values order by id")
+            .unwrap();
+        let rows: Vec<(i64, f64, i64, Option<String>)> = stmt
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        assert_eq!(
+            rows,
+            vec![(1, 1.5, 1, Some("a".into())), (2, 2.25, 0, None)]
+        );
+    }
+
+    #[test]
+    fn test_dump_paths_from_datapackage() {
+        // The datapackage is outside the working directory, so its relative
+        // resource paths only resolve against its own directory.
+        let tmp_dir = TempDir::new().unwrap();
+        write_orders_datapackage(tmp_dir.path(), "2");
+        let full_path = canonicalize(tmp_dir.path().join("orders.csv")).unwrap();
+        let full_path = full_path.to_string_lossy();
+
+        // Compression is detected on the same file.
+        let customers_path = canonicalize(tmp_dir.path().join("customers.csv")).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(
+            File::create(&customers_path).unwrap(),
+            Default::default(),
+        );
+        encoder.write_all(b"id,name\n1,a\n2,b\n").unwrap();
+        encoder.finish().unwrap();
+        let customers_path = customers_path.to_string_lossy();
+
+        let dump_file = tmp_dir.path().join("sqlite_dump.sql");
+        datapackage_to_sqlite_with_options(
+            "".into(),
+            tmp_dir.path().to_string_lossy().into(),
+            Options::builder()
+                .dump_file(dump_file.to_string_lossy().into())
+                .build(),
+        )
+        .unwrap();
+        let dump = std::fs::read_to_string(&dump_file).unwrap();
+        assert!(dump.contains(&format!(".import '{full_path}' orders --skip 1")));
+        assert!(dump.contains(&format!(".import '|gzip -dc {customers_path}' customers")));
+
+        let dump_file = tmp_dir.path().join("postgres_dump.sql");
+        datapackage_to_postgres_with_options(
+            "".into(),
+            tmp_dir.path().to_string_lossy().into(),
+            Options::builder()
+                .dump_file(dump_file.to_string_lossy().into())
+                .build(),
+        )
+        .unwrap();
+        let dump = std::fs::read_to_string(&dump_file).unwrap();
+        assert!(dump.contains(&format!("from '{full_path}' WITH")));
+        assert!(dump.contains(&format!("from program 'gzip -dc {customers_path}' WITH")));
+    }
+
+    #[test]
+    fn test_dump_rewritten_values_inline() {
+        let tmp_dir = TempDir::new().unwrap();
+        let options = Options::builder()
+            .date_order(Some(DateOrder::Dmy))
+            .dump_file(tmp_dir.path().join("dump.sql").to_string_lossy().into())
+            .build();
+
+        csvs_to_sqlite_with_options(
+            "".into(),
+            vec!["fixtures/day_first.csv".into()],
+            options.clone(),
+        )
+        .unwrap();
+        let dump = std::fs::read_to_string(tmp_dir.path().join("dump.sql")).unwrap();
+        assert!(!dump.contains(".import"));
+        assert!(dump.contains("(1, '2024-02-01', '12:30:01')"));
+
+        csvs_to_postgres_with_options("".into(), vec!["fixtures/day_first.csv".into()], options)
+            .unwrap();
+        let dump = std::fs::read_to_string(tmp_dir.path().join("dump.sql")).unwrap();
+        assert!(!dump.contains("\\copy"));
+        assert!(dump.contains("\"1\",\"2024-02-01\",\"12:30:01\""));
+    }
+
+    #[test]
+    fn test_dump_strict_sqlite() {
+        let tmp_dir = TempDir::new().unwrap();
+        let dump_file = tmp_dir.path().join("dump.sql");
+
+        csvs_to_sqlite_with_options(
+            "".into(),
+            vec!["fixtures/typed_values.csv".into()],
+            Options::builder()
+                .strict(true)
+                .dump_file(dump_file.to_string_lossy().into())
+                .build(),
+        )
+        .unwrap();
+
+        let dump = std::fs::read_to_string(&dump_file).unwrap();
+        assert!(!dump.contains(".import"));
+        let dump = dump.replace(".mode csv\n", "");
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(&dump).unwrap();
+        let rows: Vec<(i64, Option<String>)> = conn
+            .prepare("select active, name from typed_values order by id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        assert_eq!(rows, vec![(1, Some("a".into())), (0, None)]);
+    }
+
+    #[test]
+    fn test_dump_constraints_sqlite() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+        let dump_file = tmp.join("dump.sql");
+        std::fs::write(
+            tmp.join("scores.csv"),
+            "id,score,name,status\n1,3,alpha,open\n2,,beta,closed\n3,5,gamma,\n4,7,,open\n5,9,delta,closed\n6,4,epsilon,\n",
+        )
+        .unwrap();
+
+        csvs_to_sqlite_with_options(
+            tmp.join("live.db").to_string_lossy().into(),
+            vec![tmp.join("scores.csv")],
+            Options::builder()
+                .constraints(true)
+                .detect_enums(true)
+                .dump_file(dump_file.to_string_lossy().into())
+                .build(),
+        )
+        .unwrap();
+
+        // `.import` would load the empty cells as `''`, which fails the checks
+        // and is not one of the enum values.
+        let dump = std::fs::read_to_string(&dump_file).unwrap();
+        assert!(dump.contains("CHECK ([status] IN ('closed', 'open'))"));
+        assert!(!dump.contains(".import"));
+        let replay = std::process::Command::new("sqlite3")
+            .arg(tmp.join("replay.db"))
+            .stdin(File::open(&dump_file).unwrap())
+            .output()
+            .unwrap();
+        assert!(
+            replay.stderr.is_empty(),
+            "{}",
+            String::from_utf8_lossy(&replay.stderr)
+        );
+
+        let count = |db: &str| -> i64 {
+            Connection::open(tmp.join(db))
+                .unwrap()
+                .query_row("select count(*) from scores", [], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(count("live.db"), 6);
+        assert_eq!(count("replay.db"), 6);
+        let missing_statuses: i64 = Connection::open(tmp.join("replay.db"))
+            .unwrap()
+            .query_row("select count(*) - count(status) from scores", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(missing_statuses, 2);
+    }
+
+    #[test]
+    fn test_dump_encoding_sqlite() {
+        let tmp_dir = TempDir::new().unwrap();
+        let dump_file = tmp_dir.path().join("dump.sql");
+
+        csvs_to_sqlite_with_options(
+            "".into(),
+            vec!["fixtures/windows_1252.csv".into()],
+            Options::builder()
+                .dump_file(dump_file.to_string_lossy().into())
+                .build(),
+        )
+        .unwrap();
+
+        // `.import` can not transcode, so the rows are inline as UTF-8.
+        let dump = std::fs::read_to_string(&dump_file).unwrap();
+        assert!(!dump.contains(".import"));
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(&dump.replace(".mode csv\n", ""))
+            .unwrap();
+        let names: Vec<String> = conn
+            .prepare("select name from windows_1252")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        assert_eq!(names, vec!["José", "François", "Zoë"]);
+    }
+
     #[test]
     fn test_csvs_sqlite_no_conn() {
         let options = Options::builder()
@@ -3168,10 +7080,13 @@ mod tests {
         )
         .unwrap();
 
+        // Dumps refer to files by their full path.
+        let cwd = std::env::current_dir().unwrap();
+        let cwd = cwd.to_string_lossy();
         let file = File::open("/tmp/sqlite_dump.sql").unwrap();
         let lines: Vec<String> = std::io::BufReader::new(file)
             .lines()
-            .map(|x| x.unwrap())
+            .map(|x| x.unwrap().replace(&*cwd, "[cwd]"))
             .collect();
         insta::assert_yaml_snapshot!(lines);
     }
@@ -3343,6 +7258,145 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_described_invalid_values() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+        std::fs::copy(
+            "fixtures/invalid_values.csv",
+            tmp.join("invalid_values.csv"),
+        )
+        .unwrap();
+        describe::output_datapackage(
+            vec![tmp.join("invalid_values.csv")],
+            tmp.clone(),
+            &describe::Options::builder().max_invalid_ratio(0.2).build(),
+        )
+        .unwrap();
+        let datapackage: String = tmp.to_string_lossy().into();
+
+        datapackage_to_parquet_with_options(
+            tmp.join("parquet"),
+            datapackage.clone(),
+            Options::builder().build(),
+        )
+        .unwrap();
+
+        datapackage_to_sqlite_with_options(
+            tmp.join("sqlite.db").to_string_lossy().into(),
+            datapackage.clone(),
+            Options::builder().build(),
+        )
+        .unwrap();
+        let conn = Connection::open(tmp.join("sqlite.db")).unwrap();
+        let nulls: (i64, i64) = conn
+            .query_row(
+                "select count(*) - count(amount), count(*) - count(day) from invalid_values",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(nulls, (1, 1));
+
+        datapackage_to_postgres_with_options(
+            "postgresql://test@localhost/test".into(),
+            datapackage,
+            Options::builder()
+                .drop(true)
+                .schema("test_described_invalid".into())
+                .build(),
+        )
+        .unwrap();
+        let mut client = Client::connect("postgresql://test@localhost/test", NoTls).unwrap();
+        let nulls: i64 = client
+            .query_one(
+                "select count(*) - count(amount) from test_described_invalid.invalid_values",
+                &[],
+            )
+            .unwrap()
+            .get(0);
+        assert_eq!(nulls, 1);
+    }
+
+    #[test]
+    fn test_parquet_missing_values_from_csvs() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+
+        csvs_to_parquet_with_options(
+            tmp.join("parquet").to_string_lossy().into(),
+            vec!["fixtures/missing_values.csv".into()],
+            Options::builder()
+                .missing_values(vec!["NA".into(), "-".into(), "NULL".into()])
+                .build(),
+        )
+        .unwrap();
+
+        let file = File::open(tmp.join("parquet/missing_values.parquet")).unwrap();
+        let reader = SerializedFileReader::new(file).unwrap();
+
+        let mut data = vec![];
+        for row in reader {
+            for (name, field) in row.unwrap().get_column_iter() {
+                data.push((name.to_owned(), field.to_string()));
+            }
+        }
+        insta::assert_yaml_snapshot!(data)
+    }
+
+    #[test]
+    fn test_parquet_enums_from_csvs() {
+        use parquet::file::reader::FileReader;
+
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+
+        csvs_to_parquet_with_options(
+            tmp.join("parquet").to_string_lossy().into(),
+            vec!["fixtures/enums.csv".into()],
+            Options::builder().detect_enums(true).build(),
+        )
+        .unwrap();
+
+        let file = File::open(tmp.join("parquet/enums.parquet")).unwrap();
+        let reader = SerializedFileReader::new(file).unwrap();
+        let row_group = reader.metadata().row_group(0);
+        let dictionary_columns: Vec<bool> = row_group
+            .columns()
+            .iter()
+            .map(|column| column.dictionary_page_offset().is_some())
+            .collect();
+        assert_eq!(dictionary_columns, vec![false, true, false]);
+    }
+
+    #[test]
+    fn test_parquet_locale_numbers_from_csvs() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+
+        csvs_to_parquet_with_options(
+            tmp.join("parquet").to_string_lossy().into(),
+            vec!["fixtures/locale_numbers.csv".into()],
+            Options::builder()
+                .decimal_char(Some(','))
+                .group_char(Some('.'))
+                .bare_number(false)
+                .build(),
+        )
+        .unwrap();
+
+        let file = File::open(tmp.join("parquet/locale_numbers.parquet")).unwrap();
+        let reader = SerializedFileReader::new(file).unwrap();
+
+        let mut data = vec![];
+        for row in reader {
+            for (name, field) in row.unwrap().get_column_iter() {
+                data.push((name.to_owned(), field.to_string()));
+            }
+        }
+        insta::assert_yaml_snapshot!(data)
+    }
+
     #[test]
     fn test_parquet_all_types_from_csvs() {
         let tmp_dir = TempDir::new().unwrap();
@@ -3389,5 +7443,66 @@ mod tests {
             vec!["fixtures/parquet_date.csv".into()],
         )
         .unwrap();
+
+        // Dates in any detected format are loaded as timestamps.
+        let file = File::open(tmp.join("parquet/parquet_date.parquet")).unwrap();
+        let reader = SerializedFileReader::new(file).unwrap();
+        let row = reader.into_iter().next().unwrap().unwrap();
+        let ukdate = row
+            .get_column_iter()
+            .find(|(name, _)| *name == "ukdate")
+            .map(|(_, field)| field.clone())
+            .unwrap();
+        assert!(matches!(
+            ukdate,
+            parquet::record::Field::TimestampMicros(_)
+                | parquet::record::Field::TimestampMillis(_)
+                | parquet::record::Field::Long(_)
+        ));
+    }
+
+    #[test]
+    fn test_parquet_temporal_types_from_csvs() {
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp = tmp_dir.path().to_owned();
+
+        csvs_to_parquet_with_options(
+            tmp.join("parquet").to_string_lossy().into(),
+            vec![
+                "fixtures/day_first.csv".into(),
+                "fixtures/parquet_date.csv".into(),
+            ],
+            Options::builder()
+                .date_order(Some(DateOrder::Dmy))
+                .time_formats(vec!["%H:%M:%S".into()])
+                .build(),
+        )
+        .unwrap();
+
+        let schema = |name: &str| {
+            let file = File::open(tmp.join(format!("parquet/{name}.parquet"))).unwrap();
+            parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder::try_new(file)
+                .unwrap()
+                .schema()
+                .clone()
+        };
+        let day_first = schema("day_first");
+        assert_eq!(
+            day_first.field_with_name("date").unwrap().data_type(),
+            &DataType::Date32
+        );
+        assert_eq!(
+            day_first.field_with_name("time").unwrap().data_type(),
+            &DataType::Time64(TimeUnit::Microsecond)
+        );
+        let dates = schema("parquet_date");
+        assert_eq!(
+            dates.field_with_name("rfc3339").unwrap().data_type(),
+            &DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into()))
+        );
+        assert_eq!(
+            dates.field_with_name("nofracsec").unwrap().data_type(),
+            &DataType::Timestamp(TimeUnit::Nanosecond, None)
+        );
     }
 }
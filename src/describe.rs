@@ -1,10 +1,16 @@
 pub use crate::describe_csv::{describe as describe_csv, describe_parallel};
-pub use crate::describer::{Describer, Options as DescriberOptions};
+pub use crate::describer::{
+    DateOrder, Describer, Detector, NumberFormat, Options as DescriberOptions,
+};
+use crate::encoding;
+use crate::sniffer::{Dialect, sniff};
+use encoding_rs::{Encoding, UTF_8};
 use pathdiff::diff_paths;
 use serde_json::{Value, json};
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use thiserror::Error;
 use typed_builder::TypedBuilder;
 
@@ -18,6 +24,8 @@ pub enum DescribeError {
     FileNotExist(String),
     #[error("Error Reading CSV file")]
     CSVRead(#[from] csv::Error),
+    #[error("Unknown encoding {0}")]
+    UnknownEncoding(String),
 }
 
 #[derive(Default, Debug, TypedBuilder)]
@@ -34,45 +42,77 @@ pub struct Options {
     pub threads: usize,
     #[builder(default)]
     pub all_strings: bool,
+    #[builder(default = true)]
+    pub has_header: bool,
+    #[builder(default)]
+    pub field_names: Vec<String>,
+    #[builder(default)]
+    pub encoding: Option<String>,
+    #[builder(default)]
+    pub missing_values: Vec<String>,
+    #[builder(default)]
+    pub decimal_char: Option<char>,
+    #[builder(default)]
+    pub group_char: Option<char>,
+    #[builder(default = true)]
+    pub bare_number: bool,
+    #[builder(default)]
+    pub date_order: Option<DateOrder>,
+    #[builder(default)]
+    pub datetime_formats: Vec<String>,
+    #[builder(default)]
+    pub date_formats: Vec<String>,
+    #[builder(default)]
+    pub time_formats: Vec<String>,
+    #[builder(default)]
+    pub max_invalid_ratio: f64,
+    #[builder(default)]
+    pub type_evidence: bool,
+    #[builder(default)]
+    pub detect_keys: bool,
+    #[builder(default)]
+    pub detect_foreign_keys: bool,
+    #[builder(default)]
+    pub detect_enums: bool,
+    #[builder(default)]
+    pub constraints: bool,
+    #[builder(default)]
+    pub unique_constraints: bool,
+    #[builder(default)]
+    pub detect_formats: bool,
+    #[builder(default)]
+    pub detectors: Vec<Arc<dyn Detector>>,
 }
 
-fn simple_sniff(file: &PathBuf) -> Result<u8, DescribeError> {
-    let file = File::open(file)?;
-    let reader = BufReader::new(file);
-
-    let mut top_10 = String::new();
-
-    for line in reader.lines().take(10) {
-        top_10.push_str(&line?)
-    }
-
-    let mut found = b',';
-
-    for char in top_10.as_bytes() {
-        if [b',', b'\t', b'|', b';', b':'].contains(char) {
-            found = *char;
-            break;
+pub fn get_encoding(file: &Path, options: &Options) -> Result<&'static Encoding, DescribeError> {
+    match &options.encoding {
+        Some(label) => {
+            encoding::for_label(label).ok_or_else(|| DescribeError::UnknownEncoding(label.clone()))
         }
+        None => Ok(encoding::detect(file)?),
     }
-    Ok(found)
 }
 
 pub fn get_csv_reader_builder(
     file: PathBuf,
+    encoding: &'static Encoding,
     options: &Options,
-) -> Result<(csv::ReaderBuilder, u8, u8), DescribeError> {
-    let mut delimiter = options.delimiter.unwrap_or(b',');
-    let quote = options.quote.unwrap_or(b'"');
+) -> Result<(csv::ReaderBuilder, Dialect), DescribeError> {
+    let mut dialect = sniff(&file, encoding, options.delimiter, options.quote)?;
 
-    if options.delimiter.is_none() {
-        delimiter = simple_sniff(&file)?
-    }
+    // The header row is only sniffed along with the delimiter.
+    dialect.has_header = options.has_header && (options.delimiter.is_some() || dialect.has_header);
 
     let mut reader_builder = csv::ReaderBuilder::new();
 
-    reader_builder.delimiter(delimiter).quote(quote);
+    reader_builder
+        .delimiter(dialect.delimiter)
+        .quote(dialect.quote)
+        .double_quote(dialect.double_quote)
+        .escape(dialect.escape)
+        .has_headers(dialect.has_header);
 
-    Ok((reader_builder, delimiter, quote))
+    Ok((reader_builder, dialect))
 }
 
 pub fn describe_file(
@@ -88,29 +128,56 @@ pub fn describe_file(
         output_dir.push(".");
     }
 
-    let (csv_reader_builder, delimiter, quote) = get_csv_reader_builder(file.clone(), options)?;
+    let encoding = get_encoding(&file, options)?;
+    let (csv_reader_builder, dialect) = get_csv_reader_builder(file.clone(), encoding, options)?;
 
     let describer_options = DescriberOptions::builder()
         .mergable_stats(options.threads > 0 && (options.stats || !options.stats_csv.is_empty()))
         .stats(options.stats || !options.stats_csv.is_empty())
         .force_string(options.all_strings)
+        .missing_values(options.missing_values.clone())
+        .number_format(NumberFormat {
+            decimal_char: options.decimal_char.unwrap_or('.'),
+            group_char: options.group_char,
+            bare_number: options.bare_number,
+        })
+        .date_order(options.date_order)
+        .datetime_formats(options.datetime_formats.clone())
+        .date_formats(options.date_formats.clone())
+        .time_formats(options.time_formats.clone())
+        .max_invalid_ratio(options.max_invalid_ratio)
+        .type_evidence(options.type_evidence)
+        .track_unique(
+            options.detect_keys || options.detect_foreign_keys || options.unique_constraints,
+        )
+        .detect_enums(options.detect_enums)
+        .track_constraints(options.constraints)
+        .detect_formats(options.detect_formats)
+        .detectors(options.detectors.clone())
         .build();
 
-    let mut describe_value = if options.threads > 0 {
+    // Seeking into the middle of the file only works when it needs no transcoding.
+    let mut describe_value = if options.threads > 0 && encoding == UTF_8 {
         describe_parallel(
-            csv_reader_builder,
+            &csv_reader_builder,
             file.clone(),
             describer_options,
             options.threads,
         )?
     } else {
         describe_csv(
-            csv_reader_builder.from_path(file.clone())?,
+            csv_reader_builder.from_reader(encoding::open(&file, encoding)?),
             describer_options,
         )?
     };
 
-    let fields_value = describe_value["fields"].take();
+    let mut fields_value = describe_value["fields"].take();
+
+    if let Some(fields) = fields_value.as_array_mut() {
+        for (field, name) in fields.iter_mut().zip(options.field_names.iter()) {
+            field["name"] = name.clone().into();
+        }
+    }
 
     let relative_path = diff_paths(
         std::fs::canonicalize(&file)?,
@@ -125,10 +192,10 @@ pub fn describe_file(
 
     let file_no_extension = file_name.split('.').next();
 
-    let delimiter = String::from_utf8_lossy(&[delimiter]).to_string();
-    let quote = String::from_utf8_lossy(&[quote]).to_string();
+    let delimiter = String::from_utf8_lossy(&[dialect.delimiter]).to_string();
+    let quote = String::from_utf8_lossy(&[dialect.quote]).to_string();
 
-    let resource = json!({
+    let mut resource = json!({
         "profile": "tabular-data-resource",
         "name": file_no_extension,
         "row_count": describe_value["row_count"],
@@ -136,14 +203,325 @@ pub fn describe_file(
             "fields": fields_value
         },
         "path": relative_path,
+        "encoding": encoding.name(),
         "dialect": {
             "delimiter": delimiter,
-            "quoteChar": quote
+            "quoteChar": quote,
+            "lineTerminator": dialect.line_terminator,
         }
     });
+
+    if let Some(escape) = dialect.escape {
+        resource["dialect"]["doubleQuote"] = false.into();
+        resource["dialect"]["escapeChar"] = String::from_utf8_lossy(&[escape]).into();
+    }
+
+    if !dialect.has_header {
+        resource["dialect"]["header"] = false.into();
+    }
+
+    if describe_value["key_candidates"].is_object() {
+        let columns = |key: &str| -> Vec<usize> {
+            describe_value["key_candidates"][key]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|column| column.as_u64().map(|column| column as usize))
+                .collect()
+        };
+        let singles: Vec<Vec<usize>> = columns("unique").into_iter().map(|c| vec![c]).collect();
+        let csv_reader = csv_reader_builder.from_reader(encoding::open(&file, encoding)?);
+        let mut keys = find_keys(csv_reader, singles)?;
+        if options.unique_constraints {
+            for key in &keys {
+                resource["schema"]["fields"][key[0]]["constraints"]["unique"] = true.into();
+            }
+        }
+        let detect_keys = options.detect_keys || options.detect_foreign_keys;
+        if keys.is_empty() && detect_keys {
+            let key_like = columns("key_like");
+            let pairs: Vec<Vec<usize>> = key_like
+                .iter()
+                .enumerate()
+                .flat_map(|(num, first)| {
+                    key_like[num + 1..]
+                        .iter()
+                        .map(|second| vec![*first, *second])
+                })
+                .take(MAX_KEY_PAIRS)
+                .collect();
+            let csv_reader = csv_reader_builder.from_reader(encoding::open(&file, encoding)?);
+            keys = find_keys(csv_reader, pairs)?;
+        }
+        // The best key comes first, preferring integer columns then the leftmost.
+        let is_integer = |column: &usize| fields_value[column]["type"] == "integer";
+        keys.sort_by_key(|key| (!key.iter().all(is_integer), key.clone()));
+
+        let key_names = |key: &Vec<usize>| -> Vec<Value> {
+            key.iter()
+                .map(|column| fields_value[column]["name"].clone())
+                .collect()
+        };
+        if detect_keys && let Some((primary_key, candidate_keys)) = keys.split_first() {
+            resource["schema"]["primaryKey"] = match key_names(primary_key).as_slice() {
+                [name] => name.clone(),
+                names => names.into(),
+            };
+            if !candidate_keys.is_empty() {
+                resource["schema"]["candidateKeys"] =
+                    candidate_keys.iter().map(key_names).collect();
+            }
+        }
+    }
+
+    // Table Schema treats only the empty string as missing unless told otherwise.
+    if !options.missing_values.is_empty() {
+        let mut missing_values = vec![String::new()];
+        missing_values.extend(options.missing_values.iter().cloned());
+        resource["schema"]["missingValues"] = missing_values.into();
+    }
+
     Ok(resource)
 }
 
+const MAX_KEY_PAIRS: usize = 10;
+
+/// The candidate keys, each one or more columns, that are unique in every row.
+fn find_keys<R: std::io::Read>(
+    mut csv_reader: csv::Reader<R>,
+    keys: Vec<Vec<usize>>,
+) -> Result<Vec<Vec<usize>>, DescribeError> {
+    if keys.is_empty() {
+        return Ok(keys);
+    }
+    let mut seen: Vec<Option<HashSet<u64>>> = vec![Some(HashSet::new()); keys.len()];
+
+    let mut record = csv::StringRecord::new();
+    while csv_reader.read_record(&mut record)? {
+        for (key, seen) in keys.iter().zip(seen.iter_mut()) {
+            let Some(values) = seen else { continue };
+            let mut hasher = std::hash::DefaultHasher::new();
+            for column in key {
+                record.get(*column).unwrap_or_default().hash(&mut hasher);
+            }
+            // A hash collision can only rule a key out, never in.
+            if !values.insert(hasher.finish()) {
+                *seen = None;
+            }
+        }
+    }
+
+    Ok(keys
+        .into_iter()
+        .zip(seen)
+        .filter_map(|(key, seen)| seen.map(|_| key))
+        .collect())
+}
+
+/// Names a column referencing `key` in `resource` might go by, such as
+/// `country_id` or `_link_main`. The key name alone, like `id`, is too common
+/// to mean a reference.
+fn reference_names(resource: &str, key: &str) -> Vec<String> {
+    let mut resources = vec![resource.to_owned()];
+    if let Some(stem) = resource.strip_suffix("ies") {
+        resources.push(format!("{stem}y"));
+    }
+    for suffix in ["es", "s"] {
+        if let Some(singular) = resource.strip_suffix(suffix) {
+            resources.push(singular.to_owned());
+        }
+    }
+    let mut names = vec![];
+    for resource in resources {
+        let key = key.trim_start_matches('_');
+        names.push(format!("{resource}_{key}"));
+        names.push(format!("{resource}{key}"));
+        names.push(format!("_{key}_{resource}"));
+    }
+    names
+}
+
+/// Call `f` with each record of a file until it returns false.
+fn each_record(
+    file: &Path,
+    options: &Options,
+    mut f: impl FnMut(&csv::StringRecord) -> bool,
+) -> Result<(), DescribeError> {
+    let encoding = get_encoding(file, options)?;
+    let (csv_reader_builder, _) = get_csv_reader_builder(file.to_owned(), encoding, options)?;
+    let mut csv_reader = csv_reader_builder.from_reader(encoding::open(file, encoding)?);
+    let mut record = csv::StringRecord::new();
+    while csv_reader.read_record(&mut record)? {
+        if !f(&record) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// The value of a column, or `None` when it is empty or missing.
+fn present_value<'a>(
+    record: &'a csv::StringRecord,
+    column: usize,
+    options: &Options,
+) -> Option<&'a str> {
+    let value = record.get(column).unwrap_or_default();
+    let missing = value.is_empty()
+        || options
+            .missing_values
+            .iter()
+            .any(|missing| missing == value);
+    (!missing).then_some(value)
+}
+
+/// The single column keys of a resource: its primary key, then its candidate
+/// keys and unique columns.
+fn single_column_keys(resource: &Value) -> Vec<String> {
+    let schema = &resource["schema"];
+    let mut keys: Vec<String> = schema["primaryKey"]
+        .as_str()
+        .map(str::to_owned)
+        .into_iter()
+        .collect();
+    let candidate_keys = schema["candidateKeys"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|key| match key.as_array().map(Vec::as_slice) {
+            Some([name]) => name.as_str(),
+            _ => None,
+        });
+    let unique = schema["fields"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|field| field["constraints"]["unique"] == true)
+        .filter_map(|field| field["name"].as_str());
+    for key in candidate_keys.chain(unique) {
+        if !keys.iter().any(|existing| existing == key) {
+            keys.push(key.to_owned());
+        }
+    }
+    keys
+}
+
+/// Find columns, named after another resource's single column primary key,
+/// candidate key or unique column, whose values all appear in that key, and
+/// write them as `foreignKeys`. Each file is read at most once as a parent and
+/// once as a child.
+fn find_foreign_keys(
+    resources: &mut [Value],
+    files: &[PathBuf],
+    options: &Options,
+) -> Result<(), DescribeError> {
+    let mut keys = vec![];
+    for (num, resource) in resources.iter().enumerate() {
+        let fields: Vec<&Value> = resource["schema"]["fields"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .collect();
+        for key in single_column_keys(resource) {
+            if let Some(column) = fields
+                .iter()
+                .position(|field| field["name"] == key.as_str())
+            {
+                let key_type = fields[column]["type"].clone();
+                keys.push((num, key, column, key_type));
+            }
+        }
+    }
+
+    // The values of each key, read for all of a parent's keys at once.
+    let mut key_values: HashMap<usize, HashSet<String>> = HashMap::new();
+    let mut references: Vec<(usize, usize)> = vec![];
+
+    for child in 0..resources.len() {
+        let own_key = resources[child]["schema"]["primaryKey"].clone();
+        let fields = resources[child]["schema"]["fields"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+
+        // Each column that could reference a key, with that key.
+        let mut candidates: Vec<(usize, usize)> = vec![];
+        for (column, field) in fields.iter().enumerate() {
+            let Some(name) = field["name"].as_str() else {
+                continue;
+            };
+            for (index, (parent, key, _, key_type)) in keys.iter().enumerate() {
+                let parent_name = resources[*parent]["name"].as_str().unwrap_or_default();
+                if *parent != child
+                    && field["type"] == *key_type
+                    && own_key != name
+                    && reference_names(parent_name, key).iter().any(|n| n == name)
+                {
+                    candidates.push((column, index));
+                }
+            }
+        }
+        if candidates.is_empty() {
+            continue;
+        }
+
+        for (_, index) in &candidates {
+            if key_values.contains_key(index) {
+                continue;
+            }
+            let parent = keys[*index].0;
+            let parent_keys: Vec<(usize, usize)> = keys
+                .iter()
+                .enumerate()
+                .filter(|(_, (key_parent, ..))| *key_parent == parent)
+                .map(|(index, (_, _, column, _))| (index, *column))
+                .collect();
+            let mut values = vec![HashSet::new(); parent_keys.len()];
+            each_record(&files[parent], options, |record| {
+                for ((_, column), values) in parent_keys.iter().zip(values.iter_mut()) {
+                    if let Some(value) = present_value(record, *column, options) {
+                        values.insert(value.to_owned());
+                    }
+                }
+                true
+            })?;
+            for ((index, _), values) in parent_keys.into_iter().zip(values) {
+                key_values.insert(index, values);
+            }
+        }
+
+        let mut contained = vec![true; candidates.len()];
+        each_record(&files[child], options, |record| {
+            for ((column, index), contained) in candidates.iter().zip(contained.iter_mut()) {
+                if *contained && let Some(value) = present_value(record, *column, options) {
+                    *contained = key_values[index].contains(value);
+                }
+            }
+            contained.contains(&true)
+        })?;
+
+        let mut foreign_keys = vec![];
+        let mut referencing = vec![];
+        for ((column, index), contained) in candidates.iter().zip(contained) {
+            let parent = keys[*index].0;
+            if !contained || referencing.contains(column) || references.contains(&(parent, child)) {
+                continue;
+            }
+            let parent_name = resources[parent]["name"].as_str().unwrap_or_default();
+            referencing.push(*column);
+            references.push((child, parent));
+            foreign_keys.push(json!({
+                "fields": fields[*column]["name"],
+                "reference": {"resource": parent_name, "fields": keys[*index].1}
+            }));
+        }
+
+        if !foreign_keys.is_empty() {
+            resources[child]["schema"]["foreignKeys"] = foreign_keys.into();
+        }
+    }
+    Ok(())
+}
+
 pub fn describe_files(
     files: Vec<PathBuf>,
     output_dir: PathBuf,
@@ -151,10 +529,14 @@ pub fn describe_files(
 ) -> Result<Value, DescribeError> {
     let mut resources = vec![];
 
-    for file in files {
-        let resource = describe_file(file, output_dir.clone(), options)?;
+    for file in files.iter() {
+        let resource = describe_file(file.clone(), output_dir.clone(), options)?;
         resources.push(resource);
     }
+
+    if options.detect_foreign_keys {
+        find_foreign_keys(&mut resources, &files, options)?;
+    }
     let datapackage = json!({
         "profile": "tabular-data-package",
         "resources": resources
@@ -394,6 +776,312 @@ mod tests {
         insta::assert_yaml_snapshot!(describe);
     }
 
+    #[test]
+    fn test_no_header() {
+        let options = Options::builder().has_header(false).build();
+        let describe =
+            describe_files(vec!["fixtures/no_header.csv".into()], "".into(), &options).unwrap();
+        insta::assert_yaml_snapshot!(describe);
+
+        let options = Options::builder()
+            .has_header(false)
+            .threads(2)
+            .field_names(vec!["id".into(), "name".into()])
+            .build();
+        let describe =
+            describe_files(vec!["fixtures/no_header.csv".into()], "".into(), &options).unwrap();
+        insta::assert_yaml_snapshot!(describe);
+    }
+
+    #[test]
+    fn test_encoding() {
+        let options = Options::builder().build();
+        let describe = describe_files(
+            vec!["fixtures/windows_1252.csv".into()],
+            "".into(),
+            &options,
+        )
+        .unwrap();
+        insta::assert_yaml_snapshot!(describe);
+
+        let options = Options::builder().encoding(Some("latin1".into())).build();
+        let describe = describe_files(
+            vec!["fixtures/windows_1252.csv".into()],
+            "".into(),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(describe["resources"][0]["encoding"], "windows-1252");
+
+        let options = Options::builder().encoding(Some("nope".into())).build();
+        assert!(
+            describe_files(
+                vec!["fixtures/windows_1252.csv".into()],
+                "".into(),
+                &options
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_date_order() {
+        let options = Options::builder()
+            .date_order(Some(DateOrder::Dmy))
+            .time_formats(vec!["%H:%M:%S".into()])
+            .build();
+        let describe =
+            describe_files(vec!["fixtures/day_first.csv".into()], "".into(), &options).unwrap();
+        let fields = &describe["resources"][0]["schema"]["fields"];
+        assert_eq!(fields[1]["format"], "%d/%m/%Y");
+        assert_eq!(fields[2]["type"], "time");
+        assert_eq!(fields[2]["format"], "%H:%M:%S");
+    }
+
+    #[test]
+    fn test_user_format_priority() {
+        for date_formats in [["%Y/%m/%d", "%Y/%d/%m"], ["%Y/%d/%m", "%Y/%m/%d"]] {
+            let options = Options::builder()
+                .date_formats(date_formats.map(String::from).to_vec())
+                .threads(2)
+                .build();
+            let describe = describe_files(
+                vec!["fixtures/user_formats.csv".into()],
+                "".into(),
+                &options,
+            )
+            .unwrap();
+            let fields = &describe["resources"][0]["schema"]["fields"];
+            assert_eq!(fields[1]["format"], date_formats[0]);
+        }
+    }
+
+    #[test]
+    fn test_max_invalid_ratio() {
+        let describe = describe_files(
+            vec!["fixtures/invalid_values.csv".into()],
+            "".into(),
+            &Options::builder().build(),
+        )
+        .unwrap();
+        let fields = &describe["resources"][0]["schema"]["fields"];
+        assert_eq!(fields[1]["type"], "string");
+        assert_eq!(fields[2]["type"], "string");
+
+        let options = Options::builder().max_invalid_ratio(0.2).build();
+        let describe = describe_files(
+            vec!["fixtures/invalid_values.csv".into()],
+            "".into(),
+            &options,
+        )
+        .unwrap();
+        let fields = &describe["resources"][0]["schema"]["fields"];
+        assert_eq!(fields[1]["type"], "integer");
+        assert_eq!(fields[1]["stats"]["invalid_count"], 1);
+        assert_eq!(
+            fields[1]["stats"]["invalid_values"],
+            serde_json::json!([{"row": 4, "value": "n/a"}])
+        );
+        assert_eq!(fields[2]["type"], "date");
+        assert_eq!(
+            fields[2]["stats"]["invalid_values"],
+            serde_json::json!([{"row": 7, "value": "unknown"}])
+        );
+        assert!(fields[0]["stats"].is_null());
+    }
+
+    #[test]
+    fn test_type_evidence() {
+        let options = Options::builder().type_evidence(true).threads(2).build();
+        let describe = describe_files(
+            vec!["fixtures/invalid_values.csv".into()],
+            "".into(),
+            &options,
+        )
+        .unwrap();
+        let fields = &describe["resources"][0]["schema"]["fields"];
+        assert_eq!(
+            fields[1]["type_evidence"]["integer"],
+            serde_json::json!([{"row": 4, "value": "n/a"}])
+        );
+        // The first value of each chunk rules out the other date formats.
+        let date_evidence = fields[2]["type_evidence"]["date"].as_array().unwrap();
+        assert_eq!(date_evidence[0]["row"], 1);
+        assert!(
+            date_evidence.contains(
+                &serde_json::json!({"row": 7, "value": "unknown", "formats": ["%Y-%m-%d"]})
+            )
+        );
+        assert!(fields[0]["type_evidence"]["integer"].is_null());
+    }
+
+    #[test]
+    fn test_detect_keys() {
+        for threads in [0, 2] {
+            let options = Options::builder()
+                .detect_keys(true)
+                .threads(threads)
+                .build();
+            let describe = describe_files(
+                vec!["fixtures/keys.csv".into(), "fixtures/pair_keys.csv".into()],
+                "".into(),
+                &options,
+            )
+            .unwrap();
+            let schema = &describe["resources"][0]["schema"];
+            assert_eq!(schema["primaryKey"], "id");
+            assert_eq!(schema["candidateKeys"], serde_json::json!([["code"]]));
+
+            let schema = &describe["resources"][1]["schema"];
+            assert_eq!(schema["primaryKey"], serde_json::json!(["country", "year"]));
+            assert!(schema["candidateKeys"].is_null());
+        }
+
+        let describe = describe_files(
+            vec!["fixtures/keys.csv".into()],
+            "".into(),
+            &Options::builder().build(),
+        )
+        .unwrap();
+        assert!(describe["resources"][0]["schema"]["primaryKey"].is_null());
+    }
+
+    #[test]
+    fn test_detect_foreign_keys() {
+        let options = Options::builder().detect_foreign_keys(true).build();
+        let describe = describe_files(
+            vec![
+                "fixtures/foreign_keys/cities.csv".into(),
+                "fixtures/foreign_keys/countries.csv".into(),
+                "fixtures/keys.csv".into(),
+            ],
+            "".into(),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            describe["resources"][0]["schema"]["foreignKeys"],
+            serde_json::json!([{
+                "fields": "country_id",
+                "reference": {"resource": "countries", "fields": "id"}
+            }])
+        );
+        assert!(describe["resources"][1]["schema"]["foreignKeys"].is_null());
+        assert!(describe["resources"][2]["schema"]["foreignKeys"].is_null());
+
+        // `id` is not named after a resource, and `code` is a candidate key.
+        let describe = describe_files(
+            vec![
+                "fixtures/foreign_keys/orders.csv".into(),
+                "fixtures/foreign_keys/countries.csv".into(),
+                "fixtures/keys.csv".into(),
+            ],
+            "".into(),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            describe["resources"][0]["schema"]["foreignKeys"],
+            serde_json::json!([{
+                "fields": "key_code",
+                "reference": {"resource": "keys", "fields": "code"}
+            }])
+        );
+    }
+
+    #[test]
+    fn test_detect_enums() {
+        for threads in [0, 2] {
+            let options = Options::builder()
+                .detect_enums(true)
+                .threads(threads)
+                .build();
+            let describe =
+                describe_files(vec!["fixtures/enums.csv".into()], "".into(), &options).unwrap();
+            let fields = &describe["resources"][0]["schema"]["fields"];
+            assert_eq!(
+                fields[1]["constraints"]["enum"],
+                serde_json::json!(["closed", "open", "won't fix"])
+            );
+            assert!(fields[0]["constraints"].is_null());
+            assert!(fields[2]["constraints"].is_null());
+        }
+    }
+
+    #[test]
+    fn test_detect_formats() {
+        for threads in [0, 2] {
+            let options = Options::builder()
+                .detect_formats(true)
+                .threads(threads)
+                .build();
+            let describe =
+                describe_files(vec!["fixtures/formats.csv".into()], "".into(), &options).unwrap();
+            let fields = &describe["resources"][0]["schema"]["fields"];
+            let formats: Vec<_> = fields
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|field| {
+                    (
+                        field["type"].as_str().unwrap(),
+                        field["format"].as_str().unwrap(),
+                    )
+                })
+                .collect();
+            assert_eq!(
+                formats,
+                vec![
+                    ("integer", "integer"),
+                    ("string", "uuid"),
+                    ("string", "email"),
+                    ("string", "uri"),
+                    ("string", "string"),
+                    ("string", "latlong"),
+                    ("string", "country_code"),
+                    ("string", "currency_code"),
+                    ("string", "string"),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn test_constraints() {
+        for threads in [0, 2] {
+            let options = Options::builder()
+                .constraints(true)
+                .unique_constraints(true)
+                .detect_enums(true)
+                .missing_values(vec!["NA".into()])
+                .threads(threads)
+                .build();
+            let describe = describe_files(
+                vec![
+                    "fixtures/enums.csv".into(),
+                    "fixtures/missing_values.csv".into(),
+                ],
+                "".into(),
+                &options,
+            )
+            .unwrap();
+            let fields = &describe["resources"][0]["schema"]["fields"];
+            insta::assert_yaml_snapshot!(
+                "constraints",
+                fields
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|field| &field["constraints"])
+                    .collect::<Vec<_>>()
+            );
+            let fields = &describe["resources"][1]["schema"]["fields"];
+            assert!(fields[0]["constraints"]["required"].is_null());
+            assert_eq!(fields[0]["constraints"]["maximum"], 4);
+            assert!(fields[1]["constraints"]["required"].is_null());
+        }
+    }
+
     #[test]
     fn basic_multi() {
         let options = Options::builder().stats(true).threads(8).build();
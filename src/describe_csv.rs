@@ -1,3 +1,4 @@
+use crate::compression;
 use crate::describe::Describer;
 use crate::describer::Options;
 use crossbeam_channel::unbounded;
@@ -5,29 +6,48 @@ use csv::Reader;
 use serde_json::{Value, json};
 use std::path::PathBuf;
 
-pub fn describe(mut reader: Reader<std::fs::File>, options: Options) -> Result<Value, csv::Error> {
+fn header_names<R: std::io::Read>(reader: &mut Reader<R>) -> Result<Vec<String>, csv::Error> {
+    let has_headers = reader.has_headers();
+    Ok(reader
+        .headers()?
+        .iter()
+        .enumerate()
+        .map(|(num, header)| {
+            if has_headers {
+                // a UTF-8 BOM read straight from the file ends up in the first name.
+                header.trim_start_matches('\u{feff}').to_owned()
+            } else {
+                format!("field_{}", num + 1)
+            }
+        })
+        .collect())
+}
+
+pub fn describe<R: std::io::Read>(
+    mut reader: Reader<R>,
+    options: Options,
+) -> Result<Value, csv::Error> {
     //let mut reader = csv::Reader::from_path(path).unwrap();
 
-    let mut headers = vec![];
+    let headers = header_names(&mut reader)?;
     let mut describers = vec![];
-    {
-        for header in reader.headers()? {
-            headers.push(header.to_owned());
-            let describer = Describer::new_with_options(options.clone());
-            describers.push(describer)
-        }
+    for _ in headers.iter() {
+        let describer = Describer::new_with_options(options.clone());
+        describers.push(describer)
     }
 
     let mut row_count: usize = 0;
 
     for row in reader.records() {
         let record = row?;
+        row_count += 1;
         for (index, cell) in record.iter().enumerate() {
-            describers[index].process(cell);
+            describers[index].process_row(cell, row_count);
         }
-        row_count += 1;
     }
 
+    let key_candidates = key_candidates(&mut describers, &options);
+
     let mut fields = vec![];
     for (num, mut describer) in describers.into_iter().enumerate() {
         let mut field = json!({
@@ -36,16 +56,66 @@ pub fn describe(mut reader: Reader<std::fs::File>, options: Options) -> Result<V
             "format": describer.guess_type().1,
         });
 
+        let type_name = describer.guess_type().0;
+        if ["number", "integer"].contains(&type_name.as_ref()) {
+            let field = field.as_object_mut().expect("We know its an object");
+            field.extend(options.number_format.properties());
+            field.extend(describer.numeric_properties(&type_name));
+        }
+
         if options.stats || options.mergable_stats {
             field
                 .as_object_mut()
                 .expect("We know its an object")
                 .insert("stats".into(), describer.stats());
+        } else if let Some(invalid) = describer.invalid_values() {
+            field
+                .as_object_mut()
+                .expect("We know its an object")
+                .insert("stats".into(), invalid);
+        }
+
+        if options.type_evidence {
+            field
+                .as_object_mut()
+                .expect("We know its an object")
+                .insert("type_evidence".into(), describer.type_evidence());
+        }
+
+        let mut constraints = describer.constraints();
+        if let Some(values) = describer.enum_values() {
+            constraints.insert("enum".into(), values.into());
+        }
+        if !constraints.is_empty() {
+            field["constraints"] = constraints.into();
         }
         fields.push(field);
     }
 
-    Ok(json!({"row_count": row_count, "fields": fields}))
+    let mut output = json!({"row_count": row_count, "fields": fields});
+    if let Some(key_candidates) = key_candidates {
+        output["key_candidates"] = key_candidates;
+    }
+    Ok(output)
+}
+
+/// Columns that might be unique on their own and columns that could be part
+/// of a key, to be checked by reading the file again.
+fn key_candidates(describers: &mut [Describer], options: &Options) -> Option<Value> {
+    if !options.track_unique {
+        return None;
+    }
+    let mut unique = vec![];
+    let mut key_like = vec![];
+    for (index, describer) in describers.iter_mut().enumerate() {
+        if describer.maybe_unique() {
+            unique.push(index);
+        }
+        if describer.key_like() {
+            key_like.push(index);
+        }
+    }
+    Some(json!({"unique": unique, "key_like": key_like}))
 }
 
 //struct Sender {
@@ -53,11 +123,19 @@ pub fn describe(mut reader: Reader<std::fs::File>, options: Options) -> Result<V
 //}
 
 pub fn describe_parallel(
-    reader_builder: csv::ReaderBuilder,
+    reader_builder: &csv::ReaderBuilder,
     file: PathBuf,
     options: Options,
     num_threads: usize,
 ) -> Result<Value, csv::Error> {
+    // Compressed files can not be seeked into, so are described in one pass.
+    if compression::detect(&file)?.is_some() {
+        return describe(
+            reader_builder.from_reader(compression::open(&file)?),
+            options,
+        );
+    }
+
     let mut wtr = std::io::Cursor::new(vec![]);
 
     {
@@ -73,17 +151,15 @@ pub fn describe_parallel(
 
     let mut reader = reader_builder.from_path(file.clone())?;
 
-    let mut headers = vec![];
-    {
-        for header in reader.headers()? {
-            headers.push(header.to_owned());
-        }
-    }
+    let headers = header_names(&mut reader)?;
+
+    // the index only has an entry for the header row when there is one.
+    let first_record = if reader.has_headers() { 1 } else { 0 };
 
     let (send, receive) = unbounded();
 
     let chunk_size = std::cmp::max((idx.len() as usize) / num_threads, 1);
-    let mut current_index = 1;
+    let mut current_index = first_record;
 
     loop {
         if idx.len() <= current_index {
@@ -93,6 +169,7 @@ pub fn describe_parallel(
         let send_clone = send.clone();
         let options_clone = options.clone();
         let pos = idx.get(current_index)?;
+        let first_row = (current_index - first_record) as usize + 1;
         let mut reader = reader_builder.from_path(file.clone())?;
         reader.seek(pos).unwrap();
 
@@ -103,7 +180,7 @@ pub fn describe_parallel(
                 describers.push(describer)
             }
 
-            for row in reader.records().into_iter().take(chunk_size) {
+            for (row_num, row) in reader.records().take(chunk_size).enumerate() {
                 let record = match row {
                     Ok(record) => record,
                     Err(error) => {
@@ -114,7 +191,7 @@ pub fn describe_parallel(
                     }
                 };
                 for (index, cell) in record.iter().enumerate() {
-                    describers[index].process(cell);
+                    describers[index].process_row(cell, first_row + row_num);
                 }
             }
             send_clone
@@ -143,6 +220,8 @@ pub fn describe_parallel(
         }
     }
 
+    let key_candidates = key_candidates(&mut all_describers, &options);
+
     let mut fields = vec![];
     for (num, mut describer) in all_describers.into_iter().enumerate() {
         let mut field = json!({
@@ -151,16 +230,47 @@ pub fn describe_parallel(
             "format": describer.guess_type().1,
         });
 
+        let type_name = describer.guess_type().0;
+        if ["number", "integer"].contains(&type_name.as_ref()) {
+            let field = field.as_object_mut().expect("just main field above");
+            field.extend(options.number_format.properties());
+            field.extend(describer.numeric_properties(&type_name));
+        }
+
         if options.stats || options.mergable_stats {
             field
                 .as_object_mut()
                 .expect("just main field above")
                 .insert("stats".into(), describer.stats());
+        } else if let Some(invalid) = describer.invalid_values() {
+            field
+                .as_object_mut()
+                .expect("just main field above")
+                .insert("stats".into(), invalid);
+        }
+
+        if options.type_evidence {
+            field
+                .as_object_mut()
+                .expect("We know its an object")
+                .insert("type_evidence".into(), describer.type_evidence());
+        }
+
+        let mut constraints = describer.constraints();
+        if let Some(values) = describer.enum_values() {
+            constraints.insert("enum".into(), values.into());
+        }
+        if !constraints.is_empty() {
+            field["constraints"] = constraints.into();
         }
         fields.push(field);
     }
 
-    Ok(json!({"row_count": idx.len() - 1,"fields": fields}))
+    let mut output = json!({"row_count": idx.len() - first_record,"fields": fields});
+    if let Some(key_candidates) = key_candidates {
+        output["key_candidates"] = key_candidates;
+    }
+    Ok(output)
 }
 
 #[cfg(test)]
@@ -179,7 +289,7 @@ mod tests {
     fn large_multi() {
         let reader_builder = csv::ReaderBuilder::new();
         let metadata_multi = describe_parallel(
-            reader_builder,
+            &reader_builder,
             "fixtures/large/csv/data.csv".into(),
             Options::builder().build(),
             8,
@@ -2,8 +2,10 @@ use pdatastructs::hyperloglog::HyperLogLog;
 use pdatastructs::num_traits::ToPrimitive;
 use pdatastructs::tdigest;
 use stats::OnlineStats;
+use std::borrow::Cow;
 use std::cmp::{max, min};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
 use typed_builder::TypedBuilder;
 
 use chrono::DateTime;
@@ -11,36 +13,96 @@ use chrono::prelude::*;
 
 use serde_json::json;
 
-fn descriptions() -> Vec<(&'static str, &'static str)> {
-    let mut output = vec![
-        ("boolean", "boolean"),
-        ("integer", "integer"),
-        ("number", "number"),
-        ("array", "array"),
-        ("object", "object"),
-        ("datetime_tz", "rfc2822"),
-        ("datetime_tz", "rfc3339"),
-    ];
+/// A type name and format a column is checked against. Built in ones are
+/// borrowed and user supplied ones owned.
+type Description = (Cow<'static, str>, Cow<'static, str>);
+
+fn builtin(type_name: &'static str, format: &'static str) -> Description {
+    (Cow::Borrowed(type_name), Cow::Borrowed(format))
+}
+
+/// A type of value, such as an account code or fiscal period, to guess
+/// alongside the built in ones. Fields it fits all values of get its
+/// `type_name`, a Table Schema type like `string` that converters map to a
+/// column type, and its `format`.
+pub trait Detector: std::fmt::Debug + Send + Sync {
+    fn type_name(&self) -> &str;
+    fn format(&self) -> &str;
+    fn check(&self, value: &str) -> bool;
+}
+
+/// Detectors come first, then user formats, in their order of priority.
+fn user_descriptions(options: &Options) -> Vec<Description> {
+    let mut output = vec![];
+    for detector in &options.detectors {
+        output.push((
+            detector.type_name().to_owned().into(),
+            detector.format().to_owned().into(),
+        ))
+    }
+    for format in &options.datetime_formats {
+        output.push(("datetime".into(), format.clone().into()))
+    }
+    for format in &options.date_formats {
+        output.push(("date".into(), format.clone().into()))
+    }
+    for format in &options.time_formats {
+        output.push(("time".into(), format.clone().into()))
+    }
+    output
+}
+
+fn descriptions(options: &Options) -> Vec<Description> {
+    let mut output = user_descriptions(options);
+    output.extend([
+        builtin("boolean", "boolean"),
+        builtin("integer", "integer"),
+        builtin("number", "number"),
+        builtin("array", "array"),
+        builtin("object", "object"),
+        builtin("datetime_tz", "rfc2822"),
+        builtin("datetime_tz", "rfc3339"),
+    ]);
 
     for datetime_format in datetime_formats() {
-        output.push(("datetime", datetime_format))
+        output.push(builtin("datetime", datetime_format))
     }
 
     for datetime_format in datetime_tz_formats() {
-        output.push(("datetime_tz", datetime_format))
+        output.push(builtin("datetime_tz", datetime_format))
     }
 
     for date_format in date_formats() {
-        output.push(("date", date_format))
+        output.push(builtin("date", date_format))
     }
 
     for time_format in time_formats() {
-        output.push(("time", time_format))
+        output.push(builtin("time", time_format))
+    }
+
+    if options.detect_formats {
+        for string_format in string_formats() {
+            output.push(builtin("string", string_format))
+        }
     }
 
     output
 }
 
+/// Formats of text fields, in order of preference when more than one fits.
+fn string_formats() -> Vec<&'static str> {
+    vec![
+        "uuid",
+        "email",
+        "uri",
+        "ipv4",
+        "ipv6",
+        "latlong",
+        "country_code",
+        "currency_code",
+    ]
+}
+
 fn datetime_formats() -> Vec<&'static str> {
     vec![
         //"%Y-%m-%d %H:%M:%S",
@@ -114,6 +176,150 @@ fn time_formats() -> Vec<&'static str> {
     vec!["%H:%M", "%I:%M:%S %p", "%I:%M %p"]
 }
 
+const NUMBER_SYMBOLS: [char; 9] = ['$', '€', '£', '¥', '₹', '₽', '₩', '¢', '%'];
+
+/// How numbers are written, as the Table Schema `decimalChar`, `groupChar` and
+/// `bareNumber` field properties.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NumberFormat {
+    pub decimal_char: char,
+    pub group_char: Option<char>,
+    pub bare_number: bool,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        NumberFormat {
+            decimal_char: '.',
+            group_char: None,
+            bare_number: true,
+        }
+    }
+}
+
+impl NumberFormat {
+    pub fn from_field(field: &serde_json::Value) -> NumberFormat {
+        let default = NumberFormat::default();
+        NumberFormat {
+            decimal_char: field["decimalChar"]
+                .as_str()
+                .and_then(|string| string.chars().next())
+                .unwrap_or(default.decimal_char),
+            group_char: field["groupChar"]
+                .as_str()
+                .and_then(|string| string.chars().next()),
+            bare_number: field["bareNumber"].as_bool().unwrap_or(default.bare_number),
+        }
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == NumberFormat::default()
+    }
+
+    /// Field properties for anything that differs from the default.
+    pub fn properties(&self) -> serde_json::Map<String, serde_json::Value> {
+        let mut properties = serde_json::Map::new();
+        if self.decimal_char != '.' {
+            properties.insert("decimalChar".into(), self.decimal_char.to_string().into());
+        }
+        if let Some(group_char) = self.group_char {
+            properties.insert("groupChar".into(), group_char.to_string().into());
+        }
+        if !self.bare_number {
+            properties.insert("bareNumber".into(), false.into());
+        }
+        properties
+    }
+
+    /// Rewrite a number in this format as one `str::parse` understands, so
+    /// `1.234,5 €` becomes `1234.5`. Percentages keep their value, `45%` is `45`.
+    pub fn normalise<'a>(&self, string: &'a str) -> Option<Cow<'a, str>> {
+        if self.is_default() {
+            return Some(Cow::Borrowed(string));
+        }
+
+        let mut number = string;
+        if !self.bare_number {
+            number =
+                number.trim_matches(|c: char| c.is_whitespace() || NUMBER_SYMBOLS.contains(&c));
+        }
+
+        let (sign, mut number) = match number.strip_prefix(['-', '+']) {
+            Some(rest) => (&number[..1], rest),
+            None => ("", number),
+        };
+        if !self.bare_number {
+            number = number
+                .trim_start_matches(|c: char| c.is_whitespace() || NUMBER_SYMBOLS.contains(&c));
+        }
+
+        let (integer, fraction) = match number.split_once(self.decimal_char) {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (number, None),
+        };
+
+        let mut output = String::with_capacity(number.len() + 1);
+        output.push_str(sign);
+
+        match self.group_char {
+            Some(group_char) if integer.contains(group_char) => {
+                // Groups are of three digits after the first, so `1,2` is not a number.
+                for (index, group) in integer.split(group_char).enumerate() {
+                    let valid = if index == 0 {
+                        (1..=3).contains(&group.len())
+                    } else {
+                        group.len() == 3
+                    };
+                    if !valid || !group.bytes().all(|byte| byte.is_ascii_digit()) {
+                        return None;
+                    }
+                    output.push_str(group);
+                }
+            }
+            _ => output.push_str(integer),
+        }
+
+        if let Some(fraction) = fraction {
+            output.push('.');
+            output.push_str(fraction);
+        }
+        Some(Cow::Owned(output))
+    }
+}
+
+/// Order of day, month and year to prefer when a date fits more than one format,
+/// like `01/02/2024`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DateOrder {
+    Dmy,
+    Mdy,
+    Ymd,
+}
+
+impl DateOrder {
+    /// The order of the day, month and year in a chrono format string.
+    fn of_format(format: &str) -> Option<DateOrder> {
+        let position = |specifiers: &[&str]| {
+            specifiers
+                .iter()
+                .filter_map(|specifier| format.find(specifier))
+                .min()
+        };
+        let day = position(&["%d", "%e"])?;
+        let month = position(&["%m", "%b", "%B"])?;
+        let year = position(&["%Y", "%y"])?;
+        if day < month && month < year {
+            Some(DateOrder::Dmy)
+        } else if month < day && day < year {
+            Some(DateOrder::Mdy)
+        } else if year < month && month < day {
+            Some(DateOrder::Ymd)
+        } else {
+            None
+        }
+    }
+}
+
 #[derive(Default, Debug, TypedBuilder, Clone)]
 pub struct Options {
     #[builder(default)]
@@ -122,13 +328,54 @@ pub struct Options {
     pub mergable_stats: bool,
     #[builder(default)]
     pub force_string: bool,
+    #[builder(default)]
+    pub missing_values: Vec<String>,
+    #[builder(default)]
+    pub number_format: NumberFormat,
+    #[builder(default)]
+    pub date_order: Option<DateOrder>,
+    #[builder(default)]
+    pub datetime_formats: Vec<String>,
+    #[builder(default)]
+    pub date_formats: Vec<String>,
+    #[builder(default)]
+    pub time_formats: Vec<String>,
+    #[builder(default)]
+    pub max_invalid_ratio: f64,
+    #[builder(default)]
+    pub type_evidence: bool,
+    #[builder(default)]
+    pub track_unique: bool,
+    #[builder(default)]
+    pub detect_enums: bool,
+    #[builder(default)]
+    pub track_constraints: bool,
+    #[builder(default)]
+    pub detect_formats: bool,
+    #[builder(default)]
+    pub detectors: Vec<Arc<dyn Detector>>,
+}
+
+const MAX_INVALID_VALUES: usize = 20;
+const MAX_EVIDENCE_VALUES: usize = 5;
+pub(crate) const MAX_ENUM_VALUES: usize = 20;
+const MAX_ENUM_LENGTH: usize = 100;
+const PRUNE_AFTER: usize = 1000;
+
+/// Values that did not fit a description, when some are tolerated.
+#[derive(Debug, Default, Clone)]
+pub struct Invalid {
+    pub count: usize,
+    pub values: Vec<(usize, String)>,
 }
 
 #[derive(Debug)]
 pub struct Describer {
     pub count: usize,
     pub empty_count: usize,
-    descriptions: Vec<(&'static str, &'static str)>,
+    descriptions: Vec<Description>,
+    invalid: HashMap<Description, Invalid>,
+    rejected: HashMap<Description, Vec<(usize, String)>>,
     pub options: Options,
     to_delete: Vec<usize>,
     no_string_stats: bool,
@@ -143,6 +390,14 @@ pub struct Describer {
     pub loglog: HyperLogLog<str>,
     pub tdigest: tdigest::TDigest<tdigest::K1>,
     pub stats: OnlineStats,
+    pub max_integer_digits: usize,
+    pub max_fraction_digits: usize,
+    pub inexact_number: bool,
+    pub min_integer: Option<i128>,
+    pub max_integer: Option<i128>,
+    pub distinct: Option<BTreeSet<String>>,
+    pub min_chars: Option<usize>,
+    pub max_chars: Option<usize>,
 }
 
 impl Describer {
@@ -156,14 +411,15 @@ impl Describer {
         let descriptions = if options.force_string {
             vec![]
         } else {
-            descriptions()
+            descriptions(&options)
         };
         return Describer {
             count: 0,
             empty_count: 0,
             descriptions,
+            invalid: HashMap::new(),
+            rejected: HashMap::new(),
             to_delete: vec![],
-            options,
             no_string_stats: false,
             unique_to_large: false,
             string_freq: counter::Counter::new(),
@@ -176,13 +432,77 @@ impl Describer {
             loglog: HyperLogLog::new(12),
             tdigest: tdigest::TDigest::new(scale_function, 1000),
             stats: OnlineStats::new(),
+            max_integer_digits: 0,
+            max_fraction_digits: 0,
+            inexact_number: false,
+            min_integer: None,
+            max_integer: None,
+            distinct: options.detect_enums.then(BTreeSet::new),
+            min_chars: None,
+            max_chars: None,
+            options,
         };
     }
 
     pub fn merge(&mut self, other: Describer) {
+        self.count += other.count;
+        self.empty_count += other.empty_count;
+
+        for (description, other_invalid) in other.invalid {
+            let invalid = self.invalid.entry(description).or_default();
+            invalid.count += other_invalid.count;
+            invalid.values.extend(other_invalid.values);
+            invalid.values.sort();
+            invalid.values.truncate(MAX_INVALID_VALUES);
+        }
+
+        for (description, other_values) in other.rejected {
+            let values = self.rejected.entry(description).or_default();
+            values.extend(other_values);
+            values.sort();
+            values.truncate(MAX_EVIDENCE_VALUES);
+        }
+
+        self.max_integer_digits = max(self.max_integer_digits, other.max_integer_digits);
+        self.max_fraction_digits = max(self.max_fraction_digits, other.max_fraction_digits);
+        self.inexact_number |= other.inexact_number;
+        self.min_integer = [self.min_integer, other.min_integer]
+            .into_iter()
+            .flatten()
+            .min();
+        self.max_integer = max(self.max_integer, other.max_integer);
+
+        self.distinct = match (self.distinct.take(), other.distinct) {
+            (Some(mut distinct), Some(other_distinct)) => {
+                distinct.extend(other_distinct);
+                (distinct.len() <= MAX_ENUM_VALUES).then_some(distinct)
+            }
+            _ => None,
+        };
+
+        if self.options.track_unique && !self.options.mergable_stats {
+            self.loglog.merge(&other.loglog);
+        }
+
+        self.min_chars = [self.min_chars, other.min_chars]
+            .into_iter()
+            .flatten()
+            .min();
+        self.max_chars = max(self.max_chars, other.max_chars);
+
+        self.max_number = match (self.max_number, other.max_number) {
+            (Some(x), Some(y)) => Some(x.max(y)),
+            (None, Some(y)) => Some(y),
+            _ => self.max_number,
+        };
+
+        self.min_number = match (self.min_number, other.min_number) {
+            (Some(x), Some(y)) => Some(x.min(y)),
+            (None, Some(y)) => Some(y),
+            _ => self.min_number,
+        };
+
         if self.options.mergable_stats {
-            self.count += other.count;
-            self.empty_count += other.empty_count;
             self.max_len = max(self.max_len, other.max_len);
             self.min_len = match (self.min_len, other.min_len) {
                 (Some(x), Some(y)) => Some(min(x, y)),
@@ -208,76 +528,293 @@ impl Describer {
             }
             self.loglog.merge(&other.loglog);
             self.sum += other.sum;
+        }
 
-            self.max_number = match (self.max_number, other.max_number) {
-                (Some(x), Some(y)) => Some(x.max(y)),
-                (None, Some(y)) => Some(y),
-                _ => self.max_number,
-            };
+        // Keep the order, which gives the priority of user formats.
+        self.descriptions
+            .retain(|description| other.descriptions.contains(description));
+    }
 
-            self.min_number = match (self.min_number, other.min_number) {
-                (Some(x), Some(y)) => Some(x.min(y)),
-                (None, Some(y)) => Some(y),
-                _ => self.min_number,
-            };
+    pub fn guess_type(&mut self) -> (Cow<'static, str>, String) {
+        match self.guess_description() {
+            Some((type_name, format)) => (type_name, format.into_owned()),
+            None => ("string".into(), "string".to_owned()),
         }
+    }
 
-        let self_desc: HashSet<_> = self.descriptions.iter().collect();
-        let other_desc: HashSet<_> = other.descriptions.iter().collect();
-        self.descriptions = self_desc
-            .intersection(&other_desc)
-            .into_iter()
-            .map(|a| **a)
-            .collect();
+    /// Descriptions that every value fits, or enough of them with `max_invalid_ratio`.
+    fn valid_descriptions(&self) -> Vec<&Description> {
+        self.descriptions
+            .iter()
+            .filter(|description| match self.invalid.get(*description) {
+                Some(invalid) => {
+                    invalid.count as f64 <= self.options.max_invalid_ratio * self.count as f64
+                }
+                None => true,
+            })
+            .collect()
     }
 
-    pub fn guess_type(&mut self) -> (&'static str, String) {
-        let matched_types: Vec<&str> = self.descriptions.iter().map(|(t, _)| *t).collect();
+    fn guess_description(&self) -> Option<Description> {
+        let valid = self.valid_descriptions();
+        for detector in &self.options.detectors {
+            if let Some(description) = valid.iter().find(|(type_name, format)| {
+                type_name == detector.type_name() && format == detector.format()
+            }) {
+                return Some((*description).clone());
+            }
+        }
+
+        // Text formats only count once no other type fits.
+        let (text_formats, descriptions): (Vec<_>, Vec<_>) = valid
+            .into_iter()
+            .partition(|(type_name, _)| type_name == "string");
+        let matched_types: Vec<&str> = descriptions.iter().map(|(t, _)| t.as_ref()).collect();
 
-        if matched_types.contains(&"boolean") {
-            return ("boolean", "boolean".to_owned());
+        for type_name in ["boolean", "integer", "number"] {
+            if matched_types.contains(&type_name) {
+                return Some(builtin(type_name, type_name));
+            }
         }
 
-        if matched_types.contains(&"integer") {
-            return ("integer", "integer".to_owned());
+        for type_name in ["datetime_tz", "datetime", "date", "time"] {
+            if !matched_types.is_empty()
+                && matched_types.iter().all(|t| *t == type_name)
+                && let Some(format) = self.preferred_format(&descriptions)
+            {
+                return Some((type_name.into(), format.clone()));
+            }
         }
 
-        if matched_types.contains(&"number") {
-            return ("number", "number".to_owned());
+        for type_name in ["object", "array"] {
+            if matched_types.len() == 1 && matched_types.contains(&type_name) {
+                return Some(builtin(type_name, type_name));
+            }
         }
 
-        if matched_types.len() == 1 && matched_types.contains(&"datetime_tz") {
-            return ("datetime", self.descriptions[0].1.to_owned());
+        if matched_types.is_empty() {
+            // string_formats() is in order of preference.
+            return string_formats()
+                .into_iter()
+                .map(|format| builtin("string", format))
+                .find(|description| text_formats.contains(&description));
         }
 
-        if matched_types.len() == 1 && matched_types.contains(&"datetime") {
-            return ("datetime", self.descriptions[0].1.to_owned());
+        None
+    }
+
+    /// Pick between date formats that all fit: a user format by priority, then
+    /// one matching `date_order`. Without either only a single format will do.
+    fn preferred_format<'a>(
+        &self,
+        descriptions: &[&'a Description],
+    ) -> Option<&'a Cow<'static, str>> {
+        if descriptions.len() == 1 {
+            return Some(&descriptions[0].1);
         }
 
-        if matched_types.len() == 1 && matched_types.contains(&"date") {
-            return ("date", self.descriptions[0].1.to_owned());
+        if let Some((_, format)) = user_descriptions(&self.options)
+            .iter()
+            .find_map(|user_format| {
+                descriptions
+                    .iter()
+                    .copied()
+                    .find(|description| *description == user_format)
+            })
+        {
+            return Some(format);
         }
 
-        if matched_types.len() == 1 && matched_types.contains(&"time") {
-            return ("time", self.descriptions[0].1.to_owned());
+        let date_order = self.options.date_order?;
+        let mut preferred = descriptions
+            .iter()
+            .copied()
+            .filter(|(_, format)| DateOrder::of_format(format) == Some(date_order));
+        match (preferred.next(), preferred.next()) {
+            (Some((_, format)), None) => Some(format),
+            _ => None,
         }
+    }
 
-        if matched_types.len() == 1 && matched_types.contains(&"object") {
-            return ("object", "object".to_owned());
+    /// Values that did not fit the guessed type, with their row numbers.
+    pub fn invalid_values(&self) -> Option<serde_json::Value> {
+        let invalid = self.invalid.get(&self.guess_description()?)?;
+        let values: Vec<serde_json::Value> = invalid
+            .values
+            .iter()
+            .map(|(row, value)| json!({"row": row, "value": value}))
+            .collect();
+        Some(json!({"invalid_count": invalid.count, "invalid_values": values}))
+    }
+
+    /// The first values that ruled out each type, grouped by type with the
+    /// formats they ruled out, e.g. `{"integer": [{"row": 48213, "value": "12a"}]}`.
+    pub fn type_evidence(&self) -> serde_json::Value {
+        let valid = self.valid_descriptions();
+        let mut rejected: Vec<_> = self
+            .rejected
+            .iter()
+            .chain(
+                self.invalid
+                    .iter()
+                    .filter(|(description, _)| !valid.contains(description))
+                    .map(|(description, invalid)| (description, &invalid.values)),
+            )
+            .collect();
+        rejected.sort();
+
+        // Each type has (row, value, formats ruled out) entries.
+        type Evidence<'a> = Vec<(usize, &'a str, Vec<&'a str>)>;
+        let mut evidence: BTreeMap<&str, Evidence> = BTreeMap::new();
+        for ((type_name, format), values) in rejected {
+            let type_name: &str = if type_name == "datetime_tz" {
+                "datetime"
+            } else {
+                type_name
+            };
+            let type_evidence = evidence.entry(type_name).or_default();
+            for (row, value) in values {
+                match type_evidence
+                    .iter_mut()
+                    .find(|(other_row, _, _)| other_row == row)
+                {
+                    Some((_, _, formats)) => formats.push(format),
+                    None => type_evidence.push((*row, value, vec![format])),
+                }
+            }
+            type_evidence.sort();
+            type_evidence.truncate(MAX_EVIDENCE_VALUES);
         }
 
-        if matched_types.len() == 1 && matched_types.contains(&"array") {
-            return ("array", "array".to_owned());
+        let evidence: serde_json::Map<String, serde_json::Value> = evidence
+            .into_iter()
+            .map(|(type_name, values)| {
+                let values = values
+                    .into_iter()
+                    .map(|(row, value, formats)| {
+                        if formats == [type_name] {
+                            json!({"row": row, "value": value})
+                        } else {
+                            json!({"row": row, "value": value, "formats": formats})
+                        }
+                    })
+                    .collect();
+                (type_name.to_owned(), serde_json::Value::Array(values))
+            })
+            .collect();
+        serde_json::Value::Object(evidence)
+    }
+
+    /// Size hints for numeric fields. Integers get the `bitWidth` (16, 32 or
+    /// 64) that fits their range, or a `precision` in digits beyond 64 bits.
+    /// Numbers written as plain decimals get a `precision` and `scale`.
+    pub fn numeric_properties(
+        &self,
+        type_name: &str,
+    ) -> serde_json::Map<String, serde_json::Value> {
+        let mut properties = serde_json::Map::new();
+        match (type_name, self.min_integer, self.max_integer) {
+            ("integer", Some(min_integer), Some(max_integer)) => {
+                let fits = |bits: u32| {
+                    let limit = 1_i128 << (bits - 1);
+                    min_integer >= -limit && max_integer < limit
+                };
+                match [16, 32, 64].into_iter().find(|bits| fits(*bits)) {
+                    Some(bits) => properties.insert("bitWidth".into(), bits.into()),
+                    None => properties
+                        .insert("precision".into(), max(self.max_integer_digits, 1).into()),
+                };
+            }
+            ("number", _, _) if !self.inexact_number && self.count > 0 => {
+                let precision = max(self.max_integer_digits + self.max_fraction_digits, 1);
+                properties.insert("precision".into(), precision.into());
+                properties.insert("scale".into(), self.max_fraction_digits.into());
+            }
+            _ => {}
         }
+        properties
+    }
 
-        ("string", "string".to_owned())
+    /// The allowed values of a text column with only a few distinct values,
+    /// each used more than once on average.
+    pub fn enum_values(&mut self) -> Option<Vec<String>> {
+        if self.guess_type().0 != "string" {
+            return None;
+        }
+        let distinct = self.distinct.as_ref()?;
+        (!distinct.is_empty() && distinct.len() * 2 <= self.count)
+            .then(|| distinct.iter().cloned().collect())
+    }
+
+    /// Table Schema constraints that every value seen meets: `required` when
+    /// there are no missing values, the range of numbers and the length of text.
+    pub fn constraints(&mut self) -> serde_json::Map<String, serde_json::Value> {
+        let mut constraints = serde_json::Map::new();
+        if !self.options.track_constraints || self.count == 0 {
+            return constraints;
+        }
+        // values that do not fit the type are loaded as missing.
+        if self.empty_count == 0 && self.invalid_values().is_none() {
+            constraints.insert("required".into(), true.into());
+        }
+        match self.guess_type().0.as_ref() {
+            "integer" => {
+                if let (Some(minimum), Some(maximum)) = (self.min_integer, self.max_integer)
+                    && let (Ok(minimum), Ok(maximum)) =
+                        (i64::try_from(minimum), i64::try_from(maximum))
+                {
+                    constraints.insert("minimum".into(), minimum.into());
+                    constraints.insert("maximum".into(), maximum.into());
+                }
+            }
+            "number" => {
+                if let (Some(minimum), Some(maximum)) = (self.min_number, self.max_number) {
+                    constraints.insert("minimum".into(), minimum.into());
+                    constraints.insert("maximum".into(), maximum.into());
+                }
+            }
+            "string" => {
+                if let (Some(min_chars), Some(max_chars)) = (self.min_chars, self.max_chars) {
+                    constraints.insert("minLength".into(), min_chars.into());
+                    constraints.insert("maxLength".into(), max_chars.into());
+                }
+            }
+            _ => {}
+        }
+        constraints
+    }
+
+    /// Whether the column could be part of a key, having a value in every row
+    /// that fits its type.
+    pub fn key_like(&mut self) -> bool {
+        let type_name = self.guess_type().0;
+        self.empty_count == 0
+            && self.count > 0
+            && !["number", "boolean", "array", "object"].contains(&type_name.as_ref())
+            && self.invalid_values().is_none()
+    }
+
+    /// Whether the column could be a key on its own, its values being near
+    /// enough all distinct by estimate.
+    pub fn maybe_unique(&mut self) -> bool {
+        self.key_like() && self.loglog.count() as f64 >= self.count as f64 * 0.9
     }
 
     pub fn stats(&mut self) -> serde_json::Value {
         if !self.options.stats && !self.options.mergable_stats {
             return serde_json::json!({});
         }
+        let mut stats = self.all_stats();
+        if let Some(serde_json::Value::Object(invalid)) = self.invalid_values() {
+            stats
+                .as_object_mut()
+                .expect("stats are an object")
+                .extend(invalid);
+        }
+        stats
+    }
 
+    fn all_stats(&mut self) -> serde_json::Value {
         let top_20 = self.string_freq.k_most_common_ordered(20);
 
         let mut deciles = vec![];
@@ -308,7 +845,7 @@ impl Describer {
         let max_string =
             String::from_utf8_lossy(self.minmax_str.max().unwrap_or(&empty)).to_string();
 
-        let is_number = ["number", "integer"].contains(&self.guess_type().0);
+        let is_number = ["number", "integer"].contains(&self.guess_type().0.as_ref());
 
         if self.options.mergable_stats {
             json!({
@@ -351,37 +888,55 @@ impl Describer {
     }
 
     fn num_stats(&mut self, number: f64) {
-        if self.options.stats && !number.is_nan() {
-            if !self.options.mergable_stats {
-                self.tdigest.insert(number);
-            }
-            self.stats.add(number);
+        if number.is_nan() {
+            return;
+        }
+        if self.options.stats || self.options.track_constraints {
             if self.max_number.is_none() {
                 self.max_number = Some(number);
                 self.min_number = Some(number);
             }
             self.max_number = Some(number.max(self.max_number.expect("number already checked")));
             self.min_number = Some(number.min(self.min_number.expect("number already checked")));
+        }
+        if self.options.stats {
+            if !self.options.mergable_stats {
+                self.tdigest.insert(number);
+            }
+            self.stats.add(number);
             self.sum += number;
         }
     }
 
     pub fn process_num(&mut self, number: f64) {
-        if !self.descriptions.contains(&("integer", "integer"))
-            || !self.descriptions.contains(&("number", "number"))
+        if !self.descriptions.contains(&builtin("integer", "integer"))
+            || !self.descriptions.contains(&builtin("number", "number"))
         {
             self.process(&number.to_string());
             return;
         }
         self.descriptions.clear();
-        self.descriptions.push(("number", "number"));
+        self.descriptions.push(builtin("number", "number"));
 
         self.count += 1;
         self.num_stats(number);
+        self.track_digits(&number.to_string());
     }
 
     pub fn process(&mut self, string: &str) {
-        if string.is_empty() {
+        let row = self.count + self.empty_count + 1;
+        self.process_row(string, row)
+    }
+
+    /// Process the value of a known row, so values that do not fit can be reported.
+    pub fn process_row(&mut self, string: &str, row: usize) {
+        if string.is_empty()
+            || self
+                .options
+                .missing_values
+                .iter()
+                .any(|value| value == string)
+        {
             self.empty_count += 1;
             return;
         }
@@ -415,135 +970,373 @@ impl Describer {
                     }
                 }
             }
+        } else if self.options.track_unique {
+            self.loglog.add(string);
         }
 
-        for num in 0usize..self.descriptions.len() {
-            let (type_name, type_description) = self.descriptions[num];
+        if self.options.track_constraints {
+            let chars = string.chars().count();
+            self.min_chars = Some(self.min_chars.map_or(chars, |c| c.min(chars)));
+            self.max_chars = Some(self.max_chars.map_or(chars, |c| c.max(chars)));
+        }
 
-            if type_name == "boolean" && !self.check_boolean(string) {
-                self.to_delete.push(num)
+        if let Some(distinct) = self.distinct.as_mut()
+            && !distinct.contains(string)
+        {
+            if distinct.len() < MAX_ENUM_VALUES && string.len() <= MAX_ENUM_LENGTH {
+                distinct.insert(string.to_owned());
+            } else {
+                self.distinct = None;
             }
+        }
 
-            if type_name == "integer" && !self.check_integer(string) {
-                self.to_delete.push(num)
+        for num in 0usize..self.descriptions.len() {
+            let (type_name, type_description) = &self.descriptions[num];
+
+            if let Some(detector) =
+                find_detector(&self.options.detectors, type_name, type_description)
+            {
+                if !detector.check(string) {
+                    self.to_delete.push(num);
+                }
+                continue;
             }
 
             if type_name == "number" {
-                if let Some(number) = self.check_number(string) {
+                if let Some(number) = check_number(string, &self.options.number_format) {
                     self.num_stats(number);
+                    self.track_digits(string);
                 } else {
                     self.to_delete.push(num);
-                    self.tdigest.clear();
                 }
+                continue;
             }
 
-            if ["datetime", "datetime_tz", "date", "time"].contains(&type_name) {
-                if !string.is_ascii() {
+            if type_name == "integer" {
+                if let Some(integer) = parse_integer(string, &self.options.number_format) {
+                    self.min_integer = Some(self.min_integer.map_or(integer, |i| i.min(integer)));
+                    self.max_integer = Some(self.max_integer.map_or(integer, |i| i.max(integer)));
+                    self.track_digits(string);
+                } else {
                     self.to_delete.push(num);
-                    continue;
                 }
+                continue;
             }
 
-            if type_name == "datetime" && !self.check_datetime(string, type_description) {
-                self.to_delete.push(num)
-            }
-
-            if type_name == "datetime_tz" && !self.check_datetime_tz(string, type_description) {
-                self.to_delete.push(num)
-            }
-
-            if type_name == "date" && !self.check_date(string, type_description) {
+            if !check_value(
+                type_name,
+                type_description,
+                string,
+                &self.options.number_format,
+            ) {
                 self.to_delete.push(num)
             }
+        }
 
-            if type_name == "time" && !self.check_time(string, type_description) {
-                self.to_delete.push(num)
+        let to_delete = std::mem::take(&mut self.to_delete);
+        for num in to_delete.iter().rev() {
+            if self.tolerate(*num, string, row) {
+                continue;
             }
-
-            if type_name == "object" && !self.check_json_object(string) {
-                self.to_delete.push(num)
+            let description = self.descriptions.remove(*num);
+            if description == builtin("number", "number") {
+                self.tdigest.clear();
             }
-
-            if type_name == "array" && !self.check_json_array(string) {
-                self.to_delete.push(num)
+            if self.options.type_evidence {
+                let values = match self.invalid.remove(&description) {
+                    Some(invalid) => invalid.values,
+                    None => vec![(row, string.to_owned())],
+                };
+                self.rejected.insert(description, values);
             }
         }
-
-        for num in self.to_delete.iter().rev() {
-            self.descriptions.remove(*num);
-        }
+        self.to_delete = to_delete;
         self.to_delete.clear()
     }
 
-    fn check_integer(&mut self, string: &str) -> bool {
-        if string.len() > 1 && string.starts_with("0") {
-            return false;
+    /// Widest integer and fractional parts seen, for precision and scale.
+    fn track_digits(&mut self, string: &str) {
+        let digits = self
+            .options
+            .number_format
+            .normalise(string)
+            .and_then(|number| decimal_digits(&number));
+        match digits {
+            Some((integer_digits, fraction_digits)) => {
+                self.max_integer_digits = max(self.max_integer_digits, integer_digits);
+                self.max_fraction_digits = max(self.max_fraction_digits, fraction_digits);
+            }
+            None => self.inexact_number = true,
         }
-        string.parse::<i64>().is_ok()
     }
 
-    fn check_number(&mut self, string: &str) -> Option<f64> {
-        if string.len() > 1 && string.starts_with("0") && !string.starts_with("0.") {
-            return None;
+    /// With `max_invalid_ratio` a value that does not fit a description is
+    /// recorded against it instead of ruling it out. Descriptions far past the
+    /// ratio are still dropped once enough values are seen, to keep checking cheap.
+    fn tolerate(&mut self, num: usize, string: &str, row: usize) -> bool {
+        if self.options.max_invalid_ratio <= 0.0 {
+            return false;
         }
-        // floats this large loose precision
-        if string.len() > 17 {
-            return None;
+        let invalid = self
+            .invalid
+            .entry(self.descriptions[num].clone())
+            .or_default();
+        invalid.count += 1;
+        if invalid.values.len() < MAX_INVALID_VALUES {
+            invalid.values.push((row, string.to_owned()));
         }
-        string.parse().ok()
+        self.count < PRUNE_AFTER
+            || invalid.count as f64 <= self.options.max_invalid_ratio * self.count as f64
     }
+}
 
-    fn check_boolean(&mut self, string: &str) -> bool {
-        if ["true", "false", "t", "f", "True", "False", "TRUE", "FALSE"].contains(&string) {
-            return true;
-        }
-        false
+/// The detector reporting a type and format, if any.
+pub fn find_detector<'a>(
+    detectors: &'a [Arc<dyn Detector>],
+    type_name: &str,
+    format: &str,
+) -> Option<&'a Arc<dyn Detector>> {
+    detectors
+        .iter()
+        .find(|detector| detector.type_name() == type_name && detector.format() == format)
+}
+
+/// Whether a value fits a type and format as guessed by a `Describer`.
+pub fn check_value(
+    type_name: &str,
+    format: &str,
+    string: &str,
+    number_format: &NumberFormat,
+) -> bool {
+    if ["datetime", "datetime_tz", "date", "time"].contains(&type_name) && !string.is_ascii() {
+        return false;
+    }
+    match type_name {
+        "boolean" => check_boolean(string),
+        "integer" => check_integer(string, number_format),
+        "number" => check_number(string, number_format).is_some(),
+        // Older datapackages have timezone aware datetimes as `datetime` too.
+        "datetime" => check_datetime(string, format) || check_datetime_tz(string, format),
+        "datetime_tz" => check_datetime_tz(string, format),
+        "date" => check_date(string, format),
+        "time" => check_time(string, format),
+        "object" => check_json_object(string),
+        "array" => check_json_array(string),
+        "string" => check_string_format(format, string),
+        _ => true,
     }
+}
 
-    fn check_datetime_tz(&mut self, string: &str, format: &str) -> bool {
-        if format == "rfc2822" {
-            DateTime::parse_from_rfc2822(string).is_ok()
-        } else if format == "rfc3339" {
-            DateTime::parse_from_rfc3339(string).is_ok()
-        } else if DateTime::parse_from_str(string, format).is_ok() {
-            true
-        } else {
-            false
-        }
+lazy_static::lazy_static! {
+    static ref EMAIL_REGEX: regex::Regex =
+        regex::Regex::new(r"^[A-Za-z0-9.!#$%&'*+/=?^_`{|}~-]+@[A-Za-z0-9](?:[A-Za-z0-9-]*[A-Za-z0-9])?(?:\.[A-Za-z0-9](?:[A-Za-z0-9-]*[A-Za-z0-9])?)+$")
+            .expect("we know the regex is fine");
+    static ref URI_REGEX: regex::Regex =
+        regex::Regex::new(r"^[A-Za-z][A-Za-z0-9+.-]*://[^\s/?#]+[^\s]*$")
+            .expect("we know the regex is fine");
+    static ref UUID_REGEX: regex::Regex =
+        regex::Regex::new(r"^[0-9A-Fa-f]{8}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{12}$")
+            .expect("we know the regex is fine");
+}
+
+const COUNTRY_CODES: &[&str] = &[
+    "AD", "AE", "AF", "AG", "AI", "AL", "AM", "AO", "AQ", "AR", "AS", "AT", "AU", "AW", "AX", "AZ",
+    "BA", "BB", "BD", "BE", "BF", "BG", "BH", "BI", "BJ", "BL", "BM", "BN", "BO", "BQ", "BR", "BS",
+    "BT", "BV", "BW", "BY", "BZ", "CA", "CC", "CD", "CF", "CG", "CH", "CI", "CK", "CL", "CM", "CN",
+    "CO", "CR", "CU", "CV", "CW", "CX", "CY", "CZ", "DE", "DJ", "DK", "DM", "DO", "DZ", "EC", "EE",
+    "EG", "EH", "ER", "ES", "ET", "FI", "FJ", "FK", "FM", "FO", "FR", "GA", "GB", "GD", "GE", "GF",
+    "GG", "GH", "GI", "GL", "GM", "GN", "GP", "GQ", "GR", "GS", "GT", "GU", "GW", "GY", "HK", "HM",
+    "HN", "HR", "HT", "HU", "ID", "IE", "IL", "IM", "IN", "IO", "IQ", "IR", "IS", "IT", "JE", "JM",
+    "JO", "JP", "KE", "KG", "KH", "KI", "KM", "KN", "KP", "KR", "KW", "KY", "KZ", "LA", "LB", "LC",
+    "LI", "LK", "LR", "LS", "LT", "LU", "LV", "LY", "MA", "MC", "MD", "ME", "MF", "MG", "MH", "MK",
+    "ML", "MM", "MN", "MO", "MP", "MQ", "MR", "MS", "MT", "MU", "MV", "MW", "MX", "MY", "MZ", "NA",
+    "NC", "NE", "NF", "NG", "NI", "NL", "NO", "NP", "NR", "NU", "NZ", "OM", "PA", "PE", "PF", "PG",
+    "PH", "PK", "PL", "PM", "PN", "PR", "PS", "PT", "PW", "PY", "QA", "RE", "RO", "RS", "RU", "RW",
+    "SA", "SB", "SC", "SD", "SE", "SG", "SH", "SI", "SJ", "SK", "SL", "SM", "SN", "SO", "SR", "SS",
+    "ST", "SV", "SX", "SY", "SZ", "TC", "TD", "TF", "TG", "TH", "TJ", "TK", "TL", "TM", "TN", "TO",
+    "TR", "TT", "TV", "TW", "TZ", "UA", "UG", "UM", "US", "UY", "UZ", "VA", "VC", "VE", "VG", "VI",
+    "VN", "VU", "WF", "WS", "YE", "YT", "ZA", "ZM", "ZW",
+];
+
+const CURRENCY_CODES: &[&str] = &[
+    "AED", "AFN", "ALL", "AMD", "ANG", "AOA", "ARS", "AUD", "AWG", "AZN", "BAM", "BBD", "BDT",
+    "BGN", "BHD", "BIF", "BMD", "BND", "BOB", "BRL", "BSD", "BTN", "BWP", "BYN", "BZD", "CAD",
+    "CDF", "CHF", "CLP", "CNY", "COP", "CRC", "CUP", "CVE", "CZK", "DJF", "DKK", "DOP", "DZD",
+    "EGP", "ERN", "ETB", "EUR", "FJD", "FKP", "GBP", "GEL", "GHS", "GIP", "GMD", "GNF", "GTQ",
+    "GYD", "HKD", "HNL", "HTG", "HUF", "IDR", "ILS", "INR", "IQD", "IRR", "ISK", "JMD", "JOD",
+    "JPY", "KES", "KGS", "KHR", "KMF", "KPW", "KRW", "KWD", "KYD", "KZT", "LAK", "LBP", "LKR",
+    "LRD", "LSL", "LYD", "MAD", "MDL", "MGA", "MKD", "MMK", "MNT", "MOP", "MRU", "MUR", "MVR",
+    "MWK", "MXN", "MYR", "MZN", "NAD", "NGN", "NIO", "NOK", "NPR", "NZD", "OMR", "PAB", "PEN",
+    "PGK", "PHP", "PKR", "PLN", "PYG", "QAR", "RON", "RSD", "RUB", "RWF", "SAR", "SBD", "SCR",
+    "SDG", "SEK", "SGD", "SHP", "SLE", "SOS", "SRD", "SSP", "STN", "SYP", "SZL", "THB", "TJS",
+    "TMT", "TND", "TOP", "TRY", "TTD", "TWD", "TZS", "UAH", "UGX", "USD", "UYU", "UZS", "VES",
+    "VND", "VUV", "WST", "XAF", "XCD", "XOF", "XPF", "YER", "ZAR", "ZMW", "ZWL",
+];
+
+/// Whether a value fits a semantic format of a text field, such as `email`.
+fn check_string_format(format: &str, string: &str) -> bool {
+    match format {
+        "uuid" => UUID_REGEX.is_match(string),
+        "email" => EMAIL_REGEX.is_match(string),
+        "uri" => URI_REGEX.is_match(string),
+        "ipv4" => string.parse::<std::net::Ipv4Addr>().is_ok(),
+        "ipv6" => string.parse::<std::net::Ipv6Addr>().is_ok(),
+        "latlong" => parse_latlong(string).is_some(),
+        "country_code" => COUNTRY_CODES.contains(&string),
+        "currency_code" => CURRENCY_CODES.contains(&string),
+        _ => true,
     }
+}
 
-    fn check_datetime(&mut self, string: &str, format: &str) -> bool {
-        NaiveDateTime::parse_from_str(string, format).is_ok()
+/// A `latitude, longitude` pair such as `51.5072, -0.1276`. Both are written
+/// as decimals so pairs of small integers like `1,2` are not taken for places.
+pub fn parse_latlong(string: &str) -> Option<(f64, f64)> {
+    let (latitude, longitude) = string.split_once(',')?;
+    if !latitude.contains('.') || !longitude.contains('.') {
+        return None;
     }
+    let latitude: f64 = latitude.trim().parse().ok()?;
+    let longitude: f64 = longitude.trim().parse().ok()?;
+    ((-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude))
+        .then_some((latitude, longitude))
+}
+
+fn check_integer(string: &str, number_format: &NumberFormat) -> bool {
+    parse_integer(string, number_format).is_some()
+}
 
-    fn check_date(&mut self, string: &str, format: &str) -> bool {
-        chrono::NaiveDate::parse_from_str(string, format).is_ok()
+/// Integers up to 38 digits, the most a decimal column can hold.
+fn parse_integer(string: &str, number_format: &NumberFormat) -> Option<i128> {
+    let string = number_format.normalise(string)?;
+    if string.len() > 1 && string.starts_with("0") {
+        return None;
     }
+    let integer = string.parse::<i128>().ok()?;
+    (integer.unsigned_abs() < 10_u128.pow(38)).then_some(integer)
+}
 
-    fn check_time(&mut self, string: &str, format: &str) -> bool {
-        chrono::NaiveTime::parse_from_str(string, format).is_ok()
+/// Digits before and after the point of a plain decimal such as `-12.50`, or
+/// `None` for exponents and the like.
+fn decimal_digits(string: &str) -> Option<(usize, usize)> {
+    let string = string.trim_start_matches(['-', '+']);
+    let (integer, fraction) = string.split_once('.').unwrap_or((string, ""));
+    if integer.is_empty() && fraction.is_empty()
+        || !integer
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|b| b.is_ascii_digit())
+    {
+        return None;
     }
+    Some((integer.trim_start_matches('0').len(), fraction.len()))
+}
 
-    fn check_json_array(&mut self, string: &str) -> bool {
-        if let Ok(value) = serde_json::from_str::<serde_json::Value>(string) {
-            if value.is_array() {
-                return true;
-            }
-            false
-        } else {
-            false
+fn check_number(string: &str, number_format: &NumberFormat) -> Option<f64> {
+    let string = number_format.normalise(string)?;
+    if string.len() > 1 && string.starts_with("0") && !string.starts_with("0.") {
+        return None;
+    }
+    // floats this large loose precision
+    if string.len() > 17 {
+        return None;
+    }
+    string.parse().ok()
+}
+
+fn check_boolean(string: &str) -> bool {
+    parse_boolean(string).is_some()
+}
+
+pub fn parse_boolean(string: &str) -> Option<bool> {
+    match string {
+        "true" | "t" | "True" | "TRUE" => Some(true),
+        "false" | "f" | "False" | "FALSE" => Some(false),
+        _ => None,
+    }
+}
+
+fn check_datetime_tz(string: &str, format: &str) -> bool {
+    parse_datetime_tz(string, format).is_some()
+}
+
+fn parse_datetime_tz(string: &str, format: &str) -> Option<DateTime<FixedOffset>> {
+    match format {
+        "rfc2822" => DateTime::parse_from_rfc2822(string).ok(),
+        "rfc3339" => DateTime::parse_from_rfc3339(string).ok(),
+        _ => DateTime::parse_from_str(string, format).ok(),
+    }
+}
+
+/// Formats that are already ISO 8601, or that Table Schema takes to be.
+#[cfg(feature = "converters")]
+const ISO_FORMATS: [&str; 6] = [
+    "",
+    "default",
+    "any",
+    "%Y-%m-%d",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "rfc3339",
+];
+
+/// Whether values of a date, datetime or time field need rewriting by `to_iso`.
+#[cfg(feature = "converters")]
+pub fn needs_iso(type_name: &str, format: &str) -> bool {
+    ["date", "datetime", "datetime_tz", "time"].contains(&type_name)
+        && !ISO_FORMATS.contains(&format)
+}
+
+/// A date, datetime or time parsed with the format it was guessed to have and
+/// written in ISO 8601, such as `2024-01-31T13:45:00` or `13:45:00`. Datetimes
+/// with a timezone keep their offset.
+#[cfg(feature = "converters")]
+pub fn to_iso(type_name: &str, format: &str, string: &str) -> Option<String> {
+    match type_name {
+        "date" => chrono::NaiveDate::parse_from_str(string, format)
+            .ok()
+            .map(|date| date.format("%Y-%m-%d").to_string()),
+        "time" => chrono::NaiveTime::parse_from_str(string, format)
+            .ok()
+            .map(|time| time.format("%H:%M:%S%.f").to_string()),
+        "datetime" | "datetime_tz" => match NaiveDateTime::parse_from_str(string, format) {
+            Ok(datetime) => Some(datetime.format("%Y-%m-%dT%H:%M:%S%.f").to_string()),
+            Err(_) => parse_datetime_tz(string, format)
+                .map(|datetime| datetime.to_rfc3339_opts(SecondsFormat::AutoSi, false)),
+        },
+        _ => None,
+    }
+}
+
+fn check_datetime(string: &str, format: &str) -> bool {
+    NaiveDateTime::parse_from_str(string, format).is_ok()
+}
+
+fn check_date(string: &str, format: &str) -> bool {
+    chrono::NaiveDate::parse_from_str(string, format).is_ok()
+}
+
+fn check_time(string: &str, format: &str) -> bool {
+    chrono::NaiveTime::parse_from_str(string, format).is_ok()
+}
+
+fn check_json_array(string: &str) -> bool {
+    if let Ok(value) = serde_json::from_str::<serde_json::Value>(string) {
+        if value.is_array() {
+            return true;
         }
+        false
+    } else {
+        false
     }
+}
 
-    fn check_json_object(&mut self, string: &str) -> bool {
-        if let Ok(value) = serde_json::from_str::<serde_json::Value>(string) {
-            if value.is_object() {
-                return true;
-            }
-            false
-        } else {
-            false
+fn check_json_object(string: &str) -> bool {
+    if let Ok(value) = serde_json::from_str::<serde_json::Value>(string) {
+        if value.is_object() {
+            return true;
         }
+        false
+    } else {
+        false
     }
 }
 
@@ -627,13 +1420,19 @@ mod tests {
     fn guess_rfc2822() {
         let mut describer = Describer::new();
         describer.process("Fri, 28 Nov 2014 21:00:09 +0900");
-        assert_eq!(describer.guess_type(), ("datetime", "rfc2822".to_owned()));
+        assert_eq!(
+            describer.guess_type(),
+            ("datetime_tz".into(), "rfc2822".to_owned())
+        );
         describer.process("Fri, 32 Nov 2014 21:00:09 +0900");
         assert_eq!(describer.guess_type().0, "string");
 
         let mut describer = Describer::new();
         describer.process("Fri, 28 Nov 2014 21:00:09 +0900");
-        assert_eq!(describer.guess_type(), ("datetime", "rfc2822".to_owned()));
+        assert_eq!(
+            describer.guess_type(),
+            ("datetime_tz".into(), "rfc2822".to_owned())
+        );
         describer.process("2014-11-28T21:00:09+09:00");
         assert_eq!(describer.guess_type().0, "string");
     }
@@ -642,13 +1441,19 @@ mod tests {
     fn guess_rfc3339() {
         let mut describer = Describer::new();
         describer.process("2014-11-28T21:00:09+09:00");
-        assert_eq!(describer.guess_type(), ("datetime", "rfc3339".to_owned()));
+        assert_eq!(
+            describer.guess_type(),
+            ("datetime_tz".into(), "rfc3339".to_owned())
+        );
         describer.process("2014-13-28T21:00:09+09:00");
         assert_eq!(describer.guess_type().0, "string");
 
         let mut describer = Describer::new();
         describer.process("2014-11-28T21:00:09+09:00");
-        assert_eq!(describer.guess_type(), ("datetime", "rfc3339".to_owned()));
+        assert_eq!(
+            describer.guess_type(),
+            ("datetime_tz".into(), "rfc3339".to_owned())
+        );
         describer.process("Fri, 28 Nov 2014 21:00:09 +0900");
         assert_eq!(describer.guess_type().0, "string");
     }
@@ -657,7 +1462,7 @@ mod tests {
     fn guess_datetime() {
         let mut describer = Describer::new();
         describer.process("2014-11-28 21:00:09+09:00");
-        assert_eq!(describer.guess_type().0, "datetime");
+        assert_eq!(describer.guess_type().0, "datetime_tz");
         describer.process("2014-13-28 21:00:09+09:00");
         assert_eq!(describer.guess_type().0, "string");
 
@@ -797,6 +1602,332 @@ mod tests {
         insta::assert_debug_snapshot!(describer.stats());
     }
 
+    #[test]
+    fn guess_with_missing_values() {
+        let options = Options::builder()
+            .stats(true)
+            .missing_values(vec!["NA".into(), "-".into()])
+            .build();
+        let mut describer = Describer::new_with_options(options);
+        describer.process("2");
+        describer.process("NA");
+        describer.process("-");
+        describer.process("");
+        assert_eq!(describer.guess_type().0, "integer");
+        assert_eq!(describer.count, 1);
+        assert_eq!(describer.empty_count, 3);
+        describer.process("N/A");
+        assert_eq!(describer.guess_type().0, "string");
+    }
+
+    #[test]
+    fn guess_locale_numbers() {
+        let number_format = NumberFormat {
+            decimal_char: ',',
+            group_char: Some('.'),
+            bare_number: false,
+        };
+        assert_eq!(number_format.normalise("1.234,56 €").unwrap(), "1234.56");
+        assert_eq!(number_format.normalise("-€0,5").unwrap(), "-0.5");
+        assert_eq!(number_format.normalise("45%").unwrap(), "45");
+        assert_eq!(number_format.normalise("1.23,4"), None);
+
+        let options = Options::builder().number_format(number_format).build();
+        let mut describer = Describer::new_with_options(options);
+        describer.process("1.000");
+        describer.process("2.500.000");
+        assert_eq!(describer.guess_type().0, "integer");
+        describer.process("12,5 €");
+        assert_eq!(describer.guess_type().0, "number");
+        describer.process("1.5");
+        assert_eq!(describer.guess_type().0, "string");
+    }
+
+    #[test]
+    fn guess_date_order() {
+        let mut describer = Describer::new();
+        describer.process("01/02/2024");
+        describer.process("03/04/2024");
+        assert_eq!(describer.guess_type().0, "string");
+
+        for (date_order, format) in [(DateOrder::Dmy, "%d/%m/%Y"), (DateOrder::Mdy, "%m/%d/%Y")] {
+            let options = Options::builder().date_order(Some(date_order)).build();
+            let mut describer = Describer::new_with_options(options);
+            describer.process("01/02/2024");
+            describer.process("03/04/2024");
+            assert_eq!(describer.guess_type(), ("date".into(), format.to_owned()));
+        }
+
+        let options = Options::builder().date_order(Some(DateOrder::Mdy)).build();
+        let mut describer = Describer::new_with_options(options);
+        describer.process("01/02/2024");
+        describer.process("31/12/2024");
+        assert_eq!(
+            describer.guess_type(),
+            ("date".into(), "%d/%m/%Y".to_owned())
+        );
+    }
+
+    #[test]
+    fn guess_user_formats() {
+        let options = Options::builder()
+            .date_formats(vec!["%d-%b-%Y".into()])
+            .time_formats(vec!["%H:%M:%S".into()])
+            .build();
+        let mut describer = Describer::new_with_options(options.clone());
+        describer.process("01-Feb-2024");
+        describer.process("13-Mar-2024");
+        assert_eq!(
+            describer.guess_type(),
+            ("date".into(), "%d-%b-%Y".to_owned())
+        );
+
+        let mut describer = Describer::new_with_options(options);
+        describer.process("12:30:01");
+        assert_eq!(
+            describer.guess_type(),
+            ("time".into(), "%H:%M:%S".to_owned())
+        );
+
+        // The first user format wins when several fit.
+        let options = Options::builder()
+            .date_formats(vec!["%Y/%d/%m".into(), "%Y/%m/%d".into()])
+            .build();
+        let mut describer = Describer::new_with_options(options);
+        describer.process("2024/01/02");
+        assert_eq!(
+            describer.guess_type(),
+            ("date".into(), "%Y/%d/%m".to_owned())
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "converters")]
+    fn iso_values() {
+        assert_eq!(
+            to_iso("date", "%d/%m/%Y", "31/01/2024").as_deref(),
+            Some("2024-01-31")
+        );
+        assert_eq!(
+            to_iso("datetime", "%m/%d/%Y %I:%M %p", "01/31/2024 01:45 PM").as_deref(),
+            Some("2024-01-31T13:45:00")
+        );
+        assert_eq!(
+            to_iso("datetime", "rfc2822", "Fri, 28 Nov 2014 21:00:09 +0900").as_deref(),
+            Some("2014-11-28T21:00:09+09:00")
+        );
+        assert_eq!(
+            to_iso("time", "%I:%M:%S %p", "01:45:30 PM").as_deref(),
+            Some("13:45:30")
+        );
+        assert_eq!(to_iso("date", "%d/%m/%Y", "2024-01-31"), None);
+        assert!(!needs_iso("date", "%Y-%m-%d"));
+        assert!(needs_iso("datetime", "%Y-%m-%d %H:%M"));
+    }
+
+    #[test]
+    fn numeric_precision() {
+        let mut describer = Describer::new();
+        describer.process("12.50");
+        describer.process("-1234.5");
+        describer.process("0.125");
+        assert_eq!(describer.guess_type().0, "number");
+        assert_eq!(
+            describer.numeric_properties("number"),
+            json!({"precision": 7, "scale": 3})
+                .as_object()
+                .unwrap()
+                .clone()
+        );
+
+        let mut describer = Describer::new();
+        describer.process("1e5");
+        assert!(describer.numeric_properties("number").is_empty());
+
+        let mut describer = Describer::new();
+        describer.process("-32768");
+        describer.process("32767");
+        assert_eq!(describer.numeric_properties("integer")["bitWidth"], 16);
+        describer.process("32768");
+        assert_eq!(describer.numeric_properties("integer")["bitWidth"], 32);
+        describer.process("9223372036854775807");
+        assert_eq!(describer.numeric_properties("integer")["bitWidth"], 64);
+
+        // Integers beyond 64 bits are still integers, with a precision.
+        describer.process("123456789012345678901234");
+        assert_eq!(describer.guess_type().0, "integer");
+        assert_eq!(describer.numeric_properties("integer")["precision"], 24);
+    }
+
+    #[test]
+    fn enum_values() {
+        let options = Options::builder().detect_enums(true).build();
+        let mut describer = Describer::new_with_options(options.clone());
+        for value in ["b", "a", "b", "", "a", "b"] {
+            describer.process(value);
+        }
+        assert_eq!(describer.enum_values(), Some(vec!["a".into(), "b".into()]));
+
+        // Mostly distinct values are not an enum.
+        let mut describer = Describer::new_with_options(options.clone());
+        for value in ["a", "b", "c", "a"] {
+            describer.process(value);
+        }
+        assert_eq!(describer.enum_values(), None);
+
+        let mut describer = Describer::new_with_options(options);
+        for num in 0..100 {
+            describer.process(&format!("value {}", num % 21));
+        }
+        assert_eq!(describer.enum_values(), None);
+    }
+
+    #[test]
+    fn guess_string_formats() {
+        let options = Options::builder().detect_formats(true).build();
+        for (values, format) in [
+            (["ann@example.com", "bob.smith@mail.example.org"], "email"),
+            (["https://example.com", "ftp://example.org/a?b=1"], "uri"),
+            (
+                [
+                    "9b2e1f3a-6c4d-4e8f-a1b2-3c4d5e6f7a8b",
+                    "0F8FAD5B-D9CB-469F-A165-70867728950E",
+                ],
+                "uuid",
+            ),
+            (["192.168.0.1", "10.0.0.255"], "ipv4"),
+            (["::1", "2001:db8::8a2e:370:7334"], "ipv6"),
+            (["51.5072, -0.1276", "-33.8688,151.2093"], "latlong"),
+            (["GB", "FR"], "country_code"),
+            (["GBP", "EUR"], "currency_code"),
+        ] {
+            let mut describer = Describer::new_with_options(options.clone());
+            for value in values {
+                describer.process(value);
+            }
+            assert_eq!(describer.guess_type(), ("string".into(), format.to_owned()));
+        }
+
+        let mut describer = Describer::new_with_options(options.clone());
+        describer.process("ann@example.com");
+        describer.process("not an email");
+        assert_eq!(
+            describer.guess_type(),
+            ("string".into(), "string".to_owned())
+        );
+
+        // Latitudes only go up to 90 and other types come first.
+        let mut describer = Describer::new_with_options(options.clone());
+        describer.process("91.5, 0.5");
+        assert_eq!(
+            describer.guess_type(),
+            ("string".into(), "string".to_owned())
+        );
+        let mut describer = Describer::new_with_options(options);
+        describer.process("12");
+        assert_eq!(describer.guess_type().0, "integer");
+
+        let mut describer = Describer::new();
+        describer.process("ann@example.com");
+        assert_eq!(
+            describer.guess_type(),
+            ("string".into(), "string".to_owned())
+        );
+    }
+
+    #[derive(Debug)]
+    struct AccountCode;
+
+    impl Detector for AccountCode {
+        fn type_name(&self) -> &str {
+            "string"
+        }
+        fn format(&self) -> &str {
+            "account_code"
+        }
+        fn check(&self, value: &str) -> bool {
+            value.strip_prefix("ACC-").is_some_and(|digits| {
+                digits.len() == 4 && digits.bytes().all(|b| b.is_ascii_digit())
+            })
+        }
+    }
+
+    #[test]
+    fn custom_detector() {
+        let options = Options::builder()
+            .detectors(vec![Arc::new(AccountCode)])
+            .build();
+        let mut describer = Describer::new_with_options(options.clone());
+        describer.process("ACC-0001");
+        describer.process("ACC-0042");
+        assert_eq!(
+            describer.guess_type(),
+            ("string".into(), "account_code".to_owned())
+        );
+        describer.process("ACC-42");
+        assert_eq!(
+            describer.guess_type(),
+            ("string".into(), "string".to_owned())
+        );
+
+        // Detectors take priority over the built in types, even after merging.
+        #[derive(Debug)]
+        struct Period;
+        impl Detector for Period {
+            fn type_name(&self) -> &str {
+                "integer"
+            }
+            fn format(&self) -> &str {
+                "yyyymm"
+            }
+            fn check(&self, value: &str) -> bool {
+                value.len() == 6
+                    && value
+                        .parse::<u32>()
+                        .is_ok_and(|n| (1..=12).contains(&(n % 100)))
+            }
+        }
+        let options = Options::builder().detectors(vec![Arc::new(Period)]).build();
+        let mut describer = Describer::new_with_options(options.clone());
+        describer.process("202401");
+        let mut other = Describer::new_with_options(options);
+        other.process("202412");
+        describer.merge(other);
+        assert_eq!(
+            describer.guess_type(),
+            ("integer".into(), "yyyymm".to_owned())
+        );
+        describer.process("202413");
+        assert_eq!(
+            describer.guess_type(),
+            ("integer".into(), "integer".to_owned())
+        );
+    }
+
+    #[test]
+    fn type_evidence() {
+        let options = Options::builder().type_evidence(true).build();
+        let mut describer = Describer::new_with_options(options);
+        describer.process("10");
+        describer.process("");
+        describer.process("12.5");
+        describer.process("12a");
+        assert_eq!(describer.guess_type().0, "string");
+
+        let evidence = describer.type_evidence();
+        assert_eq!(evidence["boolean"], json!([{"row": 1, "value": "10"}]));
+        assert_eq!(evidence["integer"], json!([{"row": 3, "value": "12.5"}]));
+        assert_eq!(evidence["number"], json!([{"row": 4, "value": "12a"}]));
+        assert_eq!(evidence["date"][0]["row"], 1);
+        assert!(evidence["date"][0]["formats"].as_array().unwrap().len() > 1);
+
+        // Types that still fit have no evidence against them.
+        let options = Options::builder().type_evidence(true).build();
+        let mut describer = Describer::new_with_options(options);
+        describer.process("10");
+        assert!(describer.type_evidence().get("integer").is_none());
+    }
+
     // #[test]
     // fn formats() {
     //     let utc: DateTime<Utc> = Utc::now();
@@ -0,0 +1,129 @@
+use crate::compression;
+use chardetng::{EncodingDetector, Iso2022JpDetection, Utf8Detection};
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE};
+use encoding_rs_io::{DecodeReaderBytes, DecodeReaderBytesBuilder};
+use std::io::Read;
+use std::path::Path;
+
+const SAMPLE_SIZE: u64 = 64 * 1024;
+
+pub type Input = DecodeReaderBytes<Box<dyn Read + Send>, Vec<u8>>;
+
+/// Look up an encoding by any of its WHATWG labels, e.g. `latin1` or `utf-16le`.
+pub fn for_label(label: &str) -> Option<&'static Encoding> {
+    Encoding::for_label(label.trim().as_bytes())
+}
+
+/// Guess the character encoding of a file from its first 64KB.
+///
+/// A BOM wins, then UTF-16 without a BOM (spotted by its zero bytes), then
+/// UTF-8 if the sample is valid, and otherwise the most likely legacy encoding
+/// such as Windows-1252.
+pub fn detect(path: &Path) -> std::io::Result<&'static Encoding> {
+    let mut sample = vec![];
+    compression::open(path)?
+        .take(SAMPLE_SIZE)
+        .read_to_end(&mut sample)?;
+    Ok(detect_sample(&sample))
+}
+
+fn detect_sample(sample: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(sample) {
+        return encoding;
+    }
+
+    let even_zeros = sample.iter().step_by(2).filter(|byte| **byte == 0).count();
+    let odd_zeros = sample
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .filter(|byte| **byte == 0)
+        .count();
+    let half = sample.len() / 2;
+    if half > 0 && even_zeros > half / 3 && odd_zeros == 0 {
+        return UTF_16BE;
+    }
+    if half > 0 && odd_zeros > half / 3 && even_zeros == 0 {
+        return UTF_16LE;
+    }
+
+    match std::str::from_utf8(sample) {
+        Ok(_) => return UTF_8,
+        // A multi-byte character cut off at the end of the sample.
+        Err(error) if error.error_len().is_none() => return UTF_8,
+        Err(_) => {}
+    }
+
+    let mut detector = EncodingDetector::new(Iso2022JpDetection::Deny);
+    detector.feed(sample, true);
+    detector.guess(None, Utf8Detection::Allow)
+}
+
+/// Decode `sample` to UTF-8, dropping any BOM.
+pub fn decode_sample(sample: &[u8], encoding: &'static Encoding) -> Vec<u8> {
+    let (decoded, _) = encoding.decode_with_bom_removal(sample);
+    decoded.into_owned().into_bytes()
+}
+
+/// Open a file as a stream of UTF-8 bytes, decompressing it, transcoding from
+/// `encoding` and stripping any BOM. UTF-8 input is passed through as is.
+pub fn open(path: &Path, encoding: &'static Encoding) -> std::io::Result<Input> {
+    let file = compression::open(path)?;
+    let mut builder = DecodeReaderBytesBuilder::new();
+    builder.strip_bom(true);
+    if encoding == UTF_8 {
+        builder.utf8_passthru(true);
+    } else {
+        builder.encoding(Some(encoding)).bom_override(true);
+    }
+    Ok(builder.build(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::WINDOWS_1252;
+
+    #[test]
+    fn detect_encodings() {
+        assert_eq!(detect_sample(b"a,b\n1,2\n"), UTF_8);
+        assert_eq!(detect_sample("a,b\ncafé,2\n".as_bytes()), UTF_8);
+        assert_eq!(detect_sample(b"\xEF\xBB\xBFa,b\n"), UTF_8);
+        assert_eq!(detect_sample(b"\xFF\xFEa\x00,\x00b\x00"), UTF_16LE);
+        assert_eq!(detect_sample(b"a\x00,\x00b\x00\n\x00"), UTF_16LE);
+        assert_eq!(detect_sample(b"\x00a\x00,\x00b\x00\n"), UTF_16BE);
+        assert_eq!(
+            detect_sample(b"name,town\nJos\xE9,M\xE1laga\nFran\xE7ois,Orl\xE9ans\n"),
+            WINDOWS_1252
+        );
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(for_label("latin1"), Some(WINDOWS_1252));
+        assert_eq!(for_label("UTF-16LE"), Some(UTF_16LE));
+        assert_eq!(for_label("not-an-encoding"), None);
+    }
+
+    #[test]
+    fn open_transcodes() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("latin.csv");
+        std::fs::write(&path, b"a\ncaf\xE9\n").unwrap();
+
+        let mut output = String::new();
+        open(&path, WINDOWS_1252)
+            .unwrap()
+            .read_to_string(&mut output)
+            .unwrap();
+        assert_eq!(output, "a\ncafé\n");
+
+        std::fs::write(&path, b"\xEF\xBB\xBFa\nb\n").unwrap();
+        let mut output = String::new();
+        open(&path, UTF_8)
+            .unwrap()
+            .read_to_string(&mut output)
+            .unwrap();
+        assert_eq!(output, "a\nb\n");
+    }
+}
@@ -15,16 +15,18 @@
 //!
 //! ## Drawbacks
 //!
-//! * CSV files currently need header rows.
 //! * Whole file needs to be on disk as whole CSV is analyzed therefore files are read twice.
 
 #[cfg(not(target_family = "wasm"))]
 #[cfg(feature = "converters")]
 mod converters;
 
+mod compression;
 mod describe;
 mod describe_csv;
 mod describer;
+mod encoding;
+mod sniffer;
 
 #[cfg(not(target_family = "wasm"))]
 #[cfg(feature = "converters")]
@@ -33,7 +35,7 @@ mod zip_dir;
 pub use describe::{
     DescribeError, Options as DescribeOptions, describe_files, make_datapackage, output_datapackage,
 };
-pub use describer::{Describer, Options as DescriberOptions};
+pub use describer::{DateOrder, Describer, Detector, NumberFormat, Options as DescriberOptions};
 
 #[cfg(feature = "converters")]
 #[cfg(not(target_family = "wasm"))]
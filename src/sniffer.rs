@@ -0,0 +1,266 @@
+use crate::compression;
+use crate::describer::Describer;
+use crate::encoding::decode_sample;
+use encoding_rs::Encoding;
+use std::io::Read;
+use std::path::Path;
+
+const SAMPLE_SIZE: u64 = 64 * 1024;
+const DELIMITERS: [u8; 5] = [b',', b'\t', b'|', b';', b':'];
+const QUOTES: [u8; 2] = [b'"', b'\''];
+
+/// CSV dialect as guessed from the start of a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dialect {
+    pub delimiter: u8,
+    pub quote: u8,
+    pub double_quote: bool,
+    pub escape: Option<u8>,
+    pub line_terminator: String,
+    pub has_header: bool,
+}
+
+/// Sniff the dialect of a CSV file from its first 64KB.
+///
+/// Each candidate delimiter and quote character is scored by how consistent the
+/// number of columns is across the sampled records, so delimiters that only
+/// appear inside quoted fields or inside values (like the colon in a time) lose
+/// out. A `delimiter` or `quote` that is given is used as is.
+pub fn sniff(
+    path: &Path,
+    encoding: &'static Encoding,
+    delimiter: Option<u8>,
+    quote: Option<u8>,
+) -> std::io::Result<Dialect> {
+    let mut raw_sample = vec![];
+    compression::open(path)?
+        .take(SAMPLE_SIZE)
+        .read_to_end(&mut raw_sample)?;
+    let truncated = raw_sample.len() as u64 == SAMPLE_SIZE;
+    let mut sample = decode_sample(&raw_sample, encoding);
+
+    // Drop any partial record at the end of the sample.
+    if truncated && let Some(last_newline) = sample.iter().rposition(|byte| *byte == b'\n') {
+        sample.truncate(last_newline + 1);
+    }
+
+    Ok(sniff_sample(&sample, delimiter, quote))
+}
+
+fn sniff_sample(sample: &[u8], delimiter: Option<u8>, quote: Option<u8>) -> Dialect {
+    let delimiters = match delimiter {
+        Some(delimiter) => vec![delimiter],
+        None => DELIMITERS.to_vec(),
+    };
+    let quotes = match quote {
+        Some(quote) => vec![quote],
+        None => QUOTES.to_vec(),
+    };
+
+    let mut best = (delimiters[0], quotes[0]);
+    let mut best_score = (false, 0_f64);
+
+    for quote in &quotes {
+        for delimiter in &delimiters {
+            let (columns, consistency) = consistency(sample, *delimiter, *quote);
+            let score = (columns > 1, consistency);
+            // Earlier candidates win ties.
+            if score > best_score {
+                best = (*delimiter, *quote);
+                best_score = score;
+            }
+        }
+    }
+    let (delimiter, quote) = best;
+
+    let escape = if backslash_escaped(sample, delimiter, quote) {
+        Some(b'\\')
+    } else {
+        None
+    };
+
+    let mut dialect = Dialect {
+        delimiter,
+        quote,
+        double_quote: escape.is_none(),
+        escape,
+        line_terminator: line_terminator(sample),
+        has_header: true,
+    };
+    dialect.has_header = has_header(sample, &dialect);
+    dialect
+}
+
+fn reader<'a>(sample: &'a [u8], dialect: &Dialect) -> csv::Reader<&'a [u8]> {
+    csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(dialect.delimiter)
+        .quote(dialect.quote)
+        .double_quote(dialect.double_quote)
+        .escape(dialect.escape)
+        .from_reader(sample)
+}
+
+/// Most common column count and the fraction of records that have it.
+fn consistency(sample: &[u8], delimiter: u8, quote: u8) -> (usize, f64) {
+    let dialect = Dialect {
+        delimiter,
+        quote,
+        double_quote: true,
+        escape: None,
+        line_terminator: "\n".into(),
+        has_header: false,
+    };
+
+    let mut counts = counter::Counter::<usize>::new();
+    let mut total = 0;
+    let mut record = csv::ByteRecord::new();
+    let mut reader = reader(sample, &dialect);
+    while let Ok(true) = reader.read_byte_record(&mut record) {
+        counts[&record.len()] += 1;
+        total += 1;
+    }
+
+    match counts.most_common_ordered().first() {
+        Some((columns, count)) => (*columns, *count as f64 / total as f64),
+        None => (0, 0_f64),
+    }
+}
+
+/// Quotes escaped with a backslash and never doubled inside a field.
+fn backslash_escaped(sample: &[u8], delimiter: u8, quote: u8) -> bool {
+    let boundary = |byte: Option<&u8>| match byte {
+        None => true,
+        Some(byte) => [delimiter, b'\n', b'\r'].contains(byte),
+    };
+
+    let mut backslashed = 0;
+    let mut doubled = 0;
+    let mut index = 0;
+    while index + 1 < sample.len() {
+        let pair = (sample[index], sample[index + 1]);
+        if pair == (b'\\', quote) {
+            backslashed += 1;
+            index += 2;
+        } else if pair == (quote, quote) {
+            // An empty quoted field is not a doubled quote.
+            let before = index.checked_sub(1).and_then(|before| sample.get(before));
+            if !(boundary(before) && boundary(sample.get(index + 2))) {
+                doubled += 1;
+            }
+            index += 2;
+        } else {
+            index += 1;
+        }
+    }
+    backslashed > 0 && doubled == 0
+}
+
+fn line_terminator(sample: &[u8]) -> String {
+    match sample.iter().position(|byte| *byte == b'\n') {
+        Some(index) if index > 0 && sample[index - 1] == b'\r' => "\r\n".into(),
+        Some(_) => "\n".into(),
+        None if sample.contains(&b'\r') => "\r".into(),
+        None => "\n".into(),
+    }
+}
+
+/// A column votes for a header when the first value does not fit the type of
+/// the values below it, and against when it does. String columns do not vote.
+/// Only a file with votes against and none for is treated as having no header.
+fn has_header(sample: &[u8], dialect: &Dialect) -> bool {
+    let records: Vec<csv::StringRecord> = reader(sample, dialect)
+        .into_records()
+        .map_while(Result::ok)
+        .collect();
+
+    if records.len() < 2 {
+        return true;
+    }
+
+    let first = &records[0];
+    let mut for_header = 0;
+    let mut against_header = 0;
+
+    for (index, first_value) in first.iter().enumerate() {
+        if first_value.is_empty() {
+            continue;
+        }
+        let mut rest = Describer::new();
+        let mut all = Describer::new();
+        all.process(first_value);
+        for record in &records[1..] {
+            let value = record.get(index).unwrap_or_default();
+            rest.process(value);
+            all.process(value);
+        }
+        let rest_type = rest.guess_type();
+        if rest_type.0 == "string" || rest.count == 0 {
+            continue;
+        }
+        if all.guess_type() == rest_type {
+            against_header += 1;
+        } else {
+            for_header += 1;
+        }
+    }
+
+    for_header > 0 || against_header == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_delimiter() {
+        let sample = b"name;note\n\"a\";\"x, y\"\n\"b\";\"z, w\"\n";
+        let dialect = sniff_sample(sample, None, None);
+        assert_eq!(dialect.delimiter, b';');
+        assert_eq!(dialect.quote, b'"');
+        assert!(dialect.has_header);
+    }
+
+    #[test]
+    fn colon_in_time() {
+        let sample = b"id,time\n1,12:30:01\n2,13:30:01\n";
+        let dialect = sniff_sample(sample, None, None);
+        assert_eq!(dialect.delimiter, b',');
+        assert_eq!(dialect.line_terminator, "\n");
+    }
+
+    #[test]
+    fn single_quote_and_crlf() {
+        let sample = b"id|note\r\n1|'a|b'\r\n2|'c|d'\r\n";
+        let dialect = sniff_sample(sample, None, None);
+        assert_eq!(dialect.delimiter, b'|');
+        assert_eq!(dialect.quote, b'\'');
+        assert_eq!(dialect.line_terminator, "\r\n");
+    }
+
+    #[test]
+    fn backslash_escape() {
+        let sample = b"id,note\n1,\"say \\\"hi\\\"\"\n2,\"\"\n";
+        let dialect = sniff_sample(sample, None, None);
+        assert_eq!(dialect.escape, Some(b'\\'));
+        assert!(!dialect.double_quote);
+
+        let sample = b"id,note\n1,\"say \"\"hi\"\"\"\n2,\"\"\n";
+        let dialect = sniff_sample(sample, None, None);
+        assert_eq!(dialect.escape, None);
+        assert!(dialect.double_quote);
+    }
+
+    #[test]
+    fn header() {
+        let dialect = sniff_sample(b"1,Ada,2005-01-12\n2,Bert,2005-01-13\n", None, None);
+        assert!(!dialect.has_header);
+
+        let dialect = sniff_sample(b"id,name,date\n2,Bert,2005-01-13\n", None, None);
+        assert!(dialect.has_header);
+
+        let dialect = sniff_sample(b"name,other\nAda,Bert\n", None, None);
+        assert!(dialect.has_header);
+    }
+}